@@ -4,7 +4,17 @@
 //! for tokens, with royalty distribution and platform fee mechanism.
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+use anchor_lang::system_program::{self, CreateAccount};
+use anchor_lang::Discriminator;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token::{
+    self, Approve, CloseAccount, FreezeAccount, InitializeAccount3, Mint, Revoke, ThawAccount, Token,
+    TokenAccount, Transfer,
+};
 use std::collections::BTreeMap;
 
 declare_id!("YourProgramIDHere");
@@ -12,6 +22,34 @@ declare_id!("YourProgramIDHere");
 /// Basis points (1/100 of a percent) used for fee calculations
 const BASIS_POINTS: u16 = 10000;
 
+/// Time after an auction ends during which the seller may reveal a hidden reserve
+const RESERVE_REVEAL_PERIOD: i64 = 86400; // 24 hours
+
+/// Length of a rental day in seconds
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Maximum number of bids in a uniform-price auction, all of which must fit
+/// in the settlement transaction
+const MAX_UNIT_BIDS: u32 = 16;
+
+/// Maximum number of different mints in a bundle listing
+const MAX_BUNDLE_ITEMS: usize = 8;
+
+/// Accounts each batch purchase needs before its royalty accounts
+const BATCH_PURCHASE_ACCOUNTS: usize = 9;
+
+/// Remaining accounts each item of a new bundle needs
+const BUNDLE_LISTING_ACCOUNTS: usize = 6;
+
+/// Maximum number of volume-based fee tiers
+const MAX_FEE_TIERS: usize = 8;
+
+/// Maximum number of cheapest listings a collection's floor book keeps
+const MAX_FLOOR_LISTINGS: usize = 16;
+
+/// Lamports paid out of the listing rent to whoever cleans up an expired listing
+const CRANK_REWARD: u64 = 1_000_000; // 0.001 SOL
+
 /// NFT Marketplace program
 #[program]
 pub mod nft_marketplace {
@@ -23,389 +61,6270 @@ pub mod nft_marketplace {
     /// * ctx - Context containing program accounts
     /// * platform_fee - Platform fee percentage (basis points, max 1000 = 10%)
     /// * secondary_fee - Additional fee for secondary sales (basis points, max 1000 = 10%)
+    /// * allow_cancel_with_bids - Whether sellers may cancel auctions that have bids
+    /// * referral_share - Share of the platform fee paid to referrers (basis points)
+    /// * treasury - Wallet that receives platform fees
+    /// * music_nft_program - Program owning the music_nft edition accounts
+    ///
+    /// The signer becomes the marketplace authority.
     pub fn initialize(
         ctx: Context<Initialize>,
         platform_fee: u16,
         secondary_fee: u16,
+        allow_cancel_with_bids: bool,
+        referral_share: u16,
+        treasury: Pubkey,
+        music_nft_program: Pubkey,
     ) -> Result<()> {
         require!(platform_fee <= 1000, ErrorCode::FeeTooHigh);
         require!(secondary_fee <= 1000, ErrorCode::FeeTooHigh);
+        require!(referral_share <= BASIS_POINTS, ErrorCode::FeeTooHigh);
         
         let marketplace = &mut ctx.accounts.marketplace;
         marketplace.platform_fee = platform_fee;
         marketplace.secondary_fee = secondary_fee;
+        marketplace.listing_count = 0;
+        marketplace.allow_cancel_with_bids = allow_cancel_with_bids;
+        marketplace.referral_share = referral_share;
+        marketplace.authority = ctx.accounts.authority.key();
+        marketplace.pending_authority = None;
+        marketplace.treasury = treasury;
+        marketplace.paused = false;
+        marketplace.music_nft_program = music_nft_program;
         marketplace.bump = *ctx.bumps.get("marketplace").unwrap();
         
         Ok(())
     }
 
-    /// Lists an NFT for sale or auction
+    /// Updates the marketplace fees
+    ///
+    /// New fees apply to listings created afterwards; existing listings keep
+    /// the platform fee they were created with.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * platform_fee - New platform fee (basis points, max 1000 = 10%)
+    /// * secondary_fee - New secondary sale fee (basis points, max 1000 = 10%)
+    /// * referral_share - New share of the platform fee paid to referrers (basis points)
+    pub fn update_fees(
+        ctx: Context<UpdateMarketplace>,
+        platform_fee: Option<u16>,
+        secondary_fee: Option<u16>,
+        referral_share: Option<u16>,
+    ) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+        
+        if let Some(platform_fee) = platform_fee {
+            require!(platform_fee <= 1000, ErrorCode::FeeTooHigh);
+            marketplace.platform_fee = platform_fee;
+        }
+        
+        if let Some(secondary_fee) = secondary_fee {
+            require!(secondary_fee <= 1000, ErrorCode::FeeTooHigh);
+            marketplace.secondary_fee = secondary_fee;
+        }
+        
+        if let Some(referral_share) = referral_share {
+            require!(referral_share <= BASIS_POINTS, ErrorCode::FeeTooHigh);
+            marketplace.referral_share = referral_share;
+        }
+        
+        emit!(FeesUpdated {
+            platform_fee: marketplace.platform_fee,
+            secondary_fee: marketplace.secondary_fee,
+            referral_share: marketplace.referral_share,
+        });
+        
+        Ok(())
+    }
+
+    /// Sets the treasury that receives platform fees
+    ///
+    /// Fees are paid straight to the treasury wallet (SOL) or to token
+    /// accounts it owns (SPL), so there is nothing left in the program to
+    /// withdraw.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * treasury - New treasury wallet
+    pub fn set_treasury(ctx: Context<UpdateMarketplace>, treasury: Pubkey) -> Result<()> {
+        ctx.accounts.marketplace.treasury = treasury;
+        
+        emit!(TreasuryUpdated { treasury });
+        
+        Ok(())
+    }
+
+    /// Pauses or resumes trading
+    ///
+    /// While paused no new listings, bids, offers or purchases are accepted.
+    /// Cancellations, refunds and auction settlement remain available.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * paused - Whether trading is paused
+    pub fn set_paused(ctx: Context<UpdateMarketplace>, paused: bool) -> Result<()> {
+        ctx.accounts.marketplace.paused = paused;
+        
+        emit!(TradingPaused { paused });
+        
+        Ok(())
+    }
+
+    /// Proposes a new marketplace authority
+    ///
+    /// The new authority takes over once it calls `accept_authority`.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * new_authority - Proposed authority
+    pub fn transfer_authority(ctx: Context<UpdateMarketplace>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.marketplace.pending_authority = Some(new_authority);
+        
+        emit!(AuthorityTransferStarted {
+            authority: ctx.accounts.authority.key(),
+            pending_authority: new_authority,
+        });
+        
+        Ok(())
+    }
+
+    /// Accepts a pending authority transfer
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let marketplace = &mut ctx.accounts.marketplace;
+        let previous_authority = marketplace.authority;
+        marketplace.authority = ctx.accounts.new_authority.key();
+        marketplace.pending_authority = None;
+        
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority: marketplace.authority,
+        });
+        
+        Ok(())
+    }
+
+    /// Sets the platform fee charged on new listings of a collection
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * collection - Registered music_nft edition, or the mint of an unregistered token
+    /// * platform_fee - Platform fee for the collection (basis points, max 1000 = 10%)
+    pub fn set_collection_fee(
+        ctx: Context<SetCollectionFee>,
+        collection: Pubkey,
+        platform_fee: u16,
+    ) -> Result<()> {
+        require!(platform_fee <= 1000, ErrorCode::FeeTooHigh);
+        
+        let collection_fee = &mut ctx.accounts.collection_fee;
+        collection_fee.collection = collection;
+        collection_fee.platform_fee = platform_fee;
+        collection_fee.bump = *ctx.bumps.get("collection_fee").unwrap();
+        
+        emit!(CollectionFeeSet {
+            collection,
+            platform_fee,
+        });
+        
+        Ok(())
+    }
+
+    /// Removes a collection's fee override
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn remove_collection_fee(ctx: Context<RemoveCollectionFee>) -> Result<()> {
+        emit!(CollectionFeeRemoved {
+            collection: ctx.accounts.collection_fee.collection,
+        });
+        
+        Ok(())
+    }
+
+    /// Replaces the volume-based fee tiers of a payment mint
+    ///
+    /// Sellers whose cumulative sale volume in `payment_mint` reaches a tier's
+    /// `min_volume` list at that tier's fee in that currency. Tiers must be
+    /// ordered by increasing `min_volume`.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * payment_mint - Payment mint the tiers apply to (the native mint for SOL)
+    /// * tiers - New fee tiers
+    pub fn set_fee_tiers(
+        ctx: Context<SetFeeTiers>,
+        payment_mint: Pubkey,
+        tiers: Vec<FeeTier>,
+    ) -> Result<()> {
+        require!(tiers.len() <= MAX_FEE_TIERS, ErrorCode::InvalidFeeTiers);
+        require!(
+            tiers.windows(2).all(|pair| pair[0].min_volume < pair[1].min_volume),
+            ErrorCode::InvalidFeeTiers
+        );
+        require!(
+            tiers.iter().all(|tier| tier.platform_fee <= 1000),
+            ErrorCode::FeeTooHigh
+        );
+        
+        let fee_schedule = &mut ctx.accounts.fee_schedule;
+        fee_schedule.payment_mint = payment_mint;
+        fee_schedule.bump = *ctx.bumps.get("fee_schedule").unwrap();
+        fee_schedule.tiers = tiers;
+        
+        emit!(FeeTiersUpdated {
+            payment_mint,
+            tiers: fee_schedule.tiers.clone(),
+        });
+        
+        Ok(())
+    }
+
+    /// Allows a mint, collection or minting program to be listed
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * asset - Mint, registered music_nft edition, or the music_nft program to allow
+    ///   every registered edition
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, asset: Pubkey) -> Result<()> {
+        let allowlist_entry = &mut ctx.accounts.allowlist_entry;
+        allowlist_entry.asset = asset;
+        allowlist_entry.bump = *ctx.bumps.get("allowlist_entry").unwrap();
+        
+        emit!(AllowlistUpdated {
+            asset,
+            allowed: true,
+        });
+        
+        Ok(())
+    }
+
+    /// Removes an allowlist entry
+    ///
+    /// Existing listings are unaffected; new listings of the asset are rejected.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        emit!(AllowlistUpdated {
+            asset: ctx.accounts.allowlist_entry.asset,
+            allowed: false,
+        });
+        
+        Ok(())
+    }
+
+    /// Registers a mint as a token of a music_nft edition
+    ///
+    /// Anyone can point a mint authority at an edition, so collection offers,
+    /// royalties, the allowlist and collection stats only trust these records.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn register_collection_mint(ctx: Context<RegisterCollectionMint>) -> Result<()> {
+        let collection_mint = &mut ctx.accounts.collection_mint;
+        collection_mint.mint = ctx.accounts.mint.key();
+        collection_mint.collection = ctx.accounts.edition.key();
+        collection_mint.bump = *ctx.bumps.get("collection_mint").unwrap();
+        
+        emit!(CollectionMintUpdated {
+            mint: collection_mint.mint,
+            collection: collection_mint.collection,
+            registered: true,
+        });
+        
+        Ok(())
+    }
+
+    /// Removes a mint's collection record
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn unregister_collection_mint(ctx: Context<UnregisterCollectionMint>) -> Result<()> {
+        emit!(CollectionMintUpdated {
+            mint: ctx.accounts.collection_mint.mint,
+            collection: ctx.accounts.collection_mint.collection,
+            registered: false,
+        });
+        
+        Ok(())
+    }
+
+    /// Lists an NFT for fixed-price sale or one of the auction types
     ///
     /// # Arguments
     /// * ctx - Context containing program accounts
     /// * nft_mint - Mint address of the NFT
-    /// * price - Fixed price for direct sale (or starting bid for auction)
+    /// * nonce - Seller-chosen nonce used to derive the listing address
+    /// * price - Fixed price for direct sale (or minimum bid for English and sealed-bid auctions)
     /// * amount - Number of tokens to list
-    /// * is_auction - Whether to create an auction listing
-    /// * auction_duration - Duration of auction in seconds (if applicable)
-    /// * custom_fee - Optional custom platform fee (overrides defaults)
+    /// * params - Sale type, auction terms, payment currency and expiry, see `ListingParams`
+    ///
+    /// SOL listings pass the native mint as `payment_mint`. English and
+    /// sealed-bid auctions must supply a `payment_vault` (SPL) or `sol_vault`
+    /// (SOL) to hold bids. The platform fee comes from the fee override of the
+    /// NFT's collection if it has one, otherwise from the seller's volume tier
+    /// in the payment mint.
+    /// The NFT must be covered by the passed allowlist entry.
+    ///
+    /// Fixed-price and Dutch listings may omit the escrow to list tokens in
+    /// place: the marketplace PDA is approved as delegate of the seller's
+    /// token account, which is also frozen while listed if the marketplace PDA
+    /// is the mint's freeze authority.
     pub fn list_nft(
         ctx: Context<ListNft>,
         nft_mint: Pubkey,
+        nonce: u64,
         price: u64,
         amount: u64,
-        is_auction: bool,
-        auction_duration: u64,
-        custom_fee: Option<u16>,
+        params: ListingParams,
     ) -> Result<()> {
+        let ListingParams {
+            kind,
+            auction_duration,
+            min_bid_increment,
+            soft_close_window,
+            reserve_price,
+            buy_now_price,
+            payment_currency,
+            expires_at,
+        } = params;
+        let is_auction = kind == ListingKind::EnglishAuction;
+        let is_sol = payment_currency == PaymentCurrency::Sol;
+        require!(
+            ctx.accounts.payment_vault.is_some() == (kind.takes_bids() && !is_sol)
+                && ctx.accounts.sol_vault.is_some() == (kind.takes_bids() && is_sol),
+            ErrorCode::InvalidPaymentVault
+        );
+        require!(
+            !is_sol || ctx.accounts.payment_mint.key() == native_mint::ID,
+            ErrorCode::InvalidPaymentCurrency
+        );
+        require!(
+            ctx.accounts.escrow_token_account.is_some()
+                || matches!(kind, ListingKind::FixedPrice | ListingKind::DutchAuction { .. }),
+            ErrorCode::DelegatedListing
+        );
+        require!(
+            is_auction || (reserve_price.is_none() && buy_now_price.is_none()),
+            ErrorCode::NotAuction
+        );
+        if let ListingKind::DutchAuction { start_price, end_price, start_time, end_time, .. } = kind {
+            require!(
+                start_price > end_price && end_price > 0 && end_time > start_time,
+                ErrorCode::InvalidDutchAuction
+            );
+        }
+        if let ListingKind::Rental { max_days } = kind {
+            require!(price > 0 && max_days > 0, ErrorCode::InvalidRentalTerms);
+        }
+        if let ListingKind::SealedBidAuction { bidding_end, reveal_end } = kind {
+            require!(
+                bidding_end > Clock::get()?.unix_timestamp && reveal_end > bidding_end,
+                ErrorCode::InvalidAuctionEnd
+            );
+        }
+        if let Some(expires_at) = expires_at {
+            require!(!kind.takes_bids(), ErrorCode::InvalidListingKind);
+            require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidExpiry);
+        }
+        let auction_duration =
+            i64::try_from(auction_duration).map_err(|_| error!(ErrorCode::InvalidAuctionDuration))?;
+        let soft_close_window =
+            i64::try_from(soft_close_window).map_err(|_| error!(ErrorCode::InvalidAuctionDuration))?;
+        if let Some(buy_now_price) = buy_now_price {
+            require!(buy_now_price >= price, ErrorCode::InvalidBuyNowPrice);
+            if let Some(ReservePrice::Public(reserve)) = reserve_price {
+                require!(buy_now_price >= reserve, ErrorCode::InvalidBuyNowPrice);
+            }
+        }
+        
+        require!(
+            ctx.accounts.allowlist_entry.allows(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.mint.key(),
+                registered_collection(&ctx.accounts.collection_mint),
+            ),
+            ErrorCode::AssetNotAllowed
+        );
+        
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection = collection_key(&ctx.accounts.mint.key(), &ctx.accounts.collection_mint);
+        collection_stats.payment_mint = ctx.accounts.payment_mint.key();
+        collection_stats.bump = *ctx.bumps.get("collection_stats").unwrap();
+        if kind == ListingKind::FixedPrice {
+            collection_stats.offer_floor(ctx.accounts.listing.key(), price);
+        }
+        
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.seller = ctx.accounts.seller.key();
+        seller_stats.payment_mint = ctx.accounts.payment_mint.key();
+        seller_stats.bump = *ctx.bumps.get("seller_stats").unwrap();
+        
         let listing = &mut ctx.accounts.listing;
-        let marketplace = &ctx.accounts.marketplace;
+        let marketplace = &mut ctx.accounts.marketplace;
         
-        // Determine fee (custom or default)
-        let fee = custom_fee.unwrap_or(marketplace.platform_fee);
-        require!(fee <= 1000, ErrorCode::FeeTooHigh);
+        // Determine fee from the collection override or the seller's volume tier
+        let fee = marketplace.listing_fee(
+            read_record::<CollectionFee>(&ctx.accounts.collection_fee).as_ref(),
+            read_record::<FeeSchedule>(&ctx.accounts.fee_schedule).as_ref(),
+            seller_stats.volume,
+        );
+        if let BidIncrement::BasisPoints(bps) = min_bid_increment {
+            require!(bps <= BASIS_POINTS, ErrorCode::InvalidBidIncrement);
+        }
         
+        // Assign the next global listing ID
+        listing.id = marketplace.listing_count;
+        marketplace.listing_count = marketplace.listing_count.checked_add(1).unwrap();
+        listing.nonce = nonce;
+        listing.bump = *ctx.bumps.get("listing").unwrap();
+        listing.escrow_bump = ctx.bumps.get("escrow_token_account").copied().unwrap_or_default();
+        listing.payment_vault_bump = ctx
+            .bumps
+            .get("payment_vault")
+            .or_else(|| ctx.bumps.get("sol_vault"))
+            .copied()
+            .unwrap_or_default();
         listing.nft_mint = nft_mint;
-        listing.price = price;
+        listing.collection = ctx.accounts.collection_stats.collection;
+        listing.payment_mint = ctx.accounts.payment_mint.key();
+        listing.payment_currency = payment_currency;
+        listing.price = match kind {
+            ListingKind::DutchAuction { start_price, .. } => start_price,
+            _ => price,
+        };
         listing.seller = *ctx.accounts.seller.key;
-        listing.is_auction = is_auction;
-        listing.auction_end = if is_auction {
-            Clock::get()?.unix_timestamp.checked_add(auction_duration as i64).unwrap()
+        listing.kind = kind;
+        listing.auction_end = if is_auction || kind == ListingKind::UniformPriceAuction {
+            Clock::get()?
+                .unix_timestamp
+                .checked_add(auction_duration)
+                .ok_or(ErrorCode::InvalidAuctionDuration)?
         } else {
             0
         };
         listing.highest_bid = 0;
+        listing.second_highest_bid = 0;
         listing.highest_bidder = None;
+        listing.bid_referral = None;
+        listing.bid_count = 0;
         listing.amount = amount;
         listing.platform_fee = fee;
-        
-        // Transfer NFT to escrow
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.seller_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.seller.to_account_info(),
+        listing.min_bid_increment = min_bid_increment;
+        listing.soft_close_window = soft_close_window;
+        listing.reserve_price = reserve_price;
+        listing.buy_now_price = buy_now_price;
+        listing.expires_at = expires_at;
+        listing.renter = None;
+        listing.rented_until = 0;
+        listing.clearing_price = None;
+        listing.delegated_account = match ctx.accounts.escrow_token_account {
+            Some(_) => None,
+            None => Some(ctx.accounts.seller_token_account.key()),
         };
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+        
+        match &ctx.accounts.escrow_token_account {
+            Some(escrow_token_account) => {
+                // Transfer NFT to escrow
+                let cpi_accounts = Transfer {
+                    from: ctx.accounts.seller_token_account.to_account_info(),
+                    to: escrow_token_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+                token::transfer(cpi_ctx, amount)?;
+            }
+            None => {
+                // Approve the marketplace to sell the tokens in place, on top
+                // of any other escrow-less listings from the same account
+                let seller_token_account = &ctx.accounts.seller_token_account;
+                let marketplace = &ctx.accounts.marketplace;
+                let approved = if seller_token_account.delegate == COption::Some(marketplace.key()) {
+                    seller_token_account.delegated_amount.checked_add(amount).unwrap()
+                } else {
+                    amount
+                };
+                thaw_delegated(marketplace, seller_token_account, &ctx.accounts.mint, &ctx.accounts.token_program)?;
+                let cpi_accounts = Approve {
+                    to: seller_token_account.to_account_info(),
+                    delegate: marketplace.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+                token::approve(cpi_ctx, approved)?;
+                freeze_delegated(
+                    marketplace,
+                    &seller_token_account.to_account_info(),
+                    &ctx.accounts.mint,
+                    &ctx.accounts.token_program,
+                )?;
+            }
+        }
         
         emit!(NftListed {
+            listing_id: listing.id,
             nft_mint,
             seller: listing.seller,
-            price,
+            price: listing.price,
             amount,
-            is_auction,
+            kind,
         });
         
         Ok(())
     }
 
-    /// Purchases an NFT at fixed price
+    /// Cancels a listing, returning escrowed tokens to the seller
+    ///
+    /// For escrow-less listings the marketplace's delegation is reduced by the
+    /// listed amount instead, and the tokens are thawed.
+    ///
+    /// Any outstanding English auction bid is credited to the bidder's refund
+    /// record; sealed-bid deposits are claimed with `withdraw_sealed_deposit`.
+    /// Auctions with active bids can only be cancelled when the marketplace
+    /// allows it.
     ///
     /// # Arguments
     /// * ctx - Context containing program accounts
-    /// * listing_id - ID of the listing to purchase from
-    /// * amount - Number of tokens to purchase
-    pub fn buy_nft(
-        ctx: Context<BuyNft>,
-        listing_id: u64,
-        amount: u64,
-    ) -> Result<()> {
-        let listing = &mut ctx.accounts.listing;
-        require!(listing.price > 0, ErrorCode::NotForSale);
-        require!(!listing.is_auction, ErrorCode::UseBidFunction);
-        require!(amount > 0 && amount <= listing.amount, ErrorCode::InvalidAmount);
+    /// * listing_id - ID of the listing to cancel
+    pub fn cancel_listing(ctx: Context<CancelListing>, listing_id: u64) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let marketplace = &ctx.accounts.marketplace;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
         
-        let total_price = listing.price.checked_mul(amount).unwrap();
+        require!(
+            listing.bid_count == 0 || marketplace.allow_cancel_with_bids,
+            ErrorCode::AuctionHasBids
+        );
+        require!(
+            listing.kind != ListingKind::UniformPriceAuction || listing.bid_count == 0,
+            ErrorCode::AuctionHasBids
+        );
+        require!(
+            !listing.is_rented(Clock::get()?.unix_timestamp),
+            ErrorCode::RentalActive
+        );
         
-        // Process payment and distribution
-        process_purchase(
-            ctx.accounts.into(),
-            listing.nft_mint,
-            total_price,
-            amount,
-            listing.platform_fee,
-        )?;
+        // Credit highest bid to the bidder's refund record if any
+        if listing.kind == ListingKind::EnglishAuction && listing.highest_bidder.is_some() {
+            let highest_bid_refund = ctx
+                .accounts
+                .highest_bid_refund
+                .as_mut()
+                .ok_or(ErrorCode::InvalidRefundAccount)?;
+            highest_bid_refund.amount = highest_bid_refund
+                .amount
+                .checked_add(listing.highest_bid)
+                .unwrap();
+        }
         
-        // Update listing
-        listing.amount = listing.amount.checked_sub(amount).unwrap();
-        if listing.amount == 0 {
-            // Close listing if all items sold
-            ctx.accounts.listing.close(ctx.accounts.buyer.to_account_info())?;
+        // Return escrowed NFTs to seller, or give up the delegation
+        match (listing.delegated_account, &ctx.accounts.escrow_token_account) {
+            (None, Some(escrow_token_account)) => {
+                release_from_vault(
+                    marketplace,
+                    escrow_token_account,
+                    &ctx.accounts.seller_token_account,
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+                close_escrow(
+                    marketplace,
+                    escrow_token_account,
+                    &ctx.accounts.seller.to_account_info(),
+                    &ctx.accounts.token_program,
+                )?;
+            }
+            (Some(delegated_account), None) if delegated_account == ctx.accounts.seller_token_account.key() => {
+                release_delegation(
+                    marketplace,
+                    &ctx.accounts.seller_token_account,
+                    &ctx.accounts.nft_mint,
+                    &ctx.accounts.seller.to_account_info(),
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+            }
+            _ => return err!(ErrorCode::InvalidEscrowAccount),
         }
         
-        emit!(NftSold {
+        if listing.kind == ListingKind::FixedPrice {
+            ctx.accounts.collection_stats.remove_listing(listing.key());
+        }
+        
+        emit!(ListingCancelled {
+            listing_id,
             nft_mint: listing.nft_mint,
-            buyer: ctx.accounts.buyer.key(),
-            price: listing.price,
-            amount,
+            seller: listing.seller,
         });
         
+        // Keep the payment vault open while refunds are pending
+        close_payment_vault_if_empty(
+            &ctx.accounts.marketplace,
+            &mut ctx.accounts.payment_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+        
         Ok(())
     }
 
-    /// Places a bid in an active auction
+    /// Updates the terms of an existing listing
+    ///
+    /// Auction terms can only be changed before the first bid is placed.
     ///
     /// # Arguments
     /// * ctx - Context containing program accounts
-    /// * listing_id - ID of the auction listing
-    /// * bid_amount - Bid amount
-    pub fn place_bid(
-        ctx: Context<PlaceBid>,
+    /// * listing_id - ID of the listing to update
+    /// * price - New fixed price (or starting bid for auction)
+    /// * auction_end - New auction end timestamp (auctions only)
+    /// * additional_amount - Number of extra tokens to add to escrow
+    /// * expires_at - New expiry timestamp (fixed-price and Dutch listings only)
+    pub fn update_listing(
+        ctx: Context<UpdateListing>,
         listing_id: u64,
-        bid_amount: u64,
+        price: Option<u64>,
+        auction_end: Option<i64>,
+        additional_amount: Option<u64>,
+        expires_at: Option<i64>,
     ) -> Result<()> {
         let listing = &mut ctx.accounts.listing;
-        require!(listing.is_auction, ErrorCode::NotAuction);
-        require!(Clock::get()?.unix_timestamp < listing.auction_end, ErrorCode::AuctionEnded);
-        require!(bid_amount > listing.highest_bid, ErrorCode::BidTooLow);
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.bid_count == 0, ErrorCode::AuctionHasBids);
         
-        // Refund previous bid if any
-        if let Some(prev_bidder) = listing.highest_bidder {
-            let prev_bid = listing.highest_bid;
+        if let Some(price) = price {
+            require!(
+                !matches!(listing.kind, ListingKind::DutchAuction { .. }),
+                ErrorCode::InvalidListingKind
+            );
+            if let ListingKind::Rental { .. } = listing.kind {
+                require!(price > 0, ErrorCode::InvalidRentalTerms);
+            }
+            listing.price = price;
+            if listing.kind == ListingKind::FixedPrice {
+                ctx.accounts.collection_stats.reprice_floor(listing.key(), price);
+            }
+        }
+        
+        if let Some(auction_end) = auction_end {
+            require!(listing.kind == ListingKind::EnglishAuction, ErrorCode::NotAuction);
+            require!(auction_end > Clock::get()?.unix_timestamp, ErrorCode::InvalidAuctionEnd);
+            listing.auction_end = auction_end;
+        }
+        
+        if let Some(expires_at) = expires_at {
+            require!(!listing.kind.takes_bids(), ErrorCode::InvalidListingKind);
+            require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidExpiry);
+            listing.expires_at = Some(expires_at);
+        }
+        
+        if let Some(additional_amount) = additional_amount {
+            require!(additional_amount > 0, ErrorCode::InvalidAmount);
+            let escrow_token_account = ctx
+                .accounts
+                .escrow_token_account
+                .as_ref()
+                .ok_or(ErrorCode::DelegatedListing)?;
+            listing.amount = listing.amount.checked_add(additional_amount).unwrap();
+            
+            // Top up escrow
             let cpi_accounts = Transfer {
-                from: ctx.accounts.escrow_token_account.to_account_info(),
-                to: ctx.accounts.prev_bidder_token_account.to_account_info(),
-                authority: ctx.accounts.marketplace.to_account_info(),
+                from: ctx.accounts.seller_token_account.to_account_info(),
+                to: escrow_token_account.to_account_info(),
+                authority: ctx.accounts.seller.to_account_info(),
             };
             let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-            token::transfer(cpi_ctx, prev_bid)?;
+            token::transfer(cpi_ctx, additional_amount)?;
         }
         
-        // Record new bid
-        listing.highest_bid = bid_amount;
-        listing.highest_bidder = Some(*ctx.accounts.bidder.key);
-        
-        // Transfer bid amount to escrow
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.bidder_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.bidder.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, bid_amount)?;
-        
-        emit!(BidPlaced {
+        emit!(ListingUpdated {
+            listing_id,
             nft_mint: listing.nft_mint,
-            bidder: ctx.accounts.bidder.key(),
-            amount: bid_amount,
+            price: listing.price,
+            amount: listing.amount,
+            auction_end: listing.auction_end,
+            expires_at: listing.expires_at,
         });
         
         Ok(())
     }
 
-    /// Finalizes an ended auction
+    /// Cleans up an expired listing, returning escrowed tokens to the seller
+    ///
+    /// Anyone may call this once the listing has expired. The caller is paid
+    /// `CRANK_REWARD` lamports out of the listing rent and the rest of the rent
+    /// goes back to the seller. Escrow-less listings also need the seller's
+    /// signature, since only the token owner can reduce the marketplace's
+    /// delegation; tokens of other listings stay frozen.
     ///
     /// # Arguments
     /// * ctx - Context containing program accounts
-    /// * listing_id - ID of the auction listing
-    pub fn finalize_auction(
-        ctx: Context<FinalizeAuction>,
-        listing_id: u64,
-    ) -> Result<()> {
+    /// * listing_id - ID of the expired listing
+    pub fn crank_expired_listing(ctx: Context<CrankExpiredListing>, listing_id: u64) -> Result<()> {
         let listing = &ctx.accounts.listing;
-        require!(listing.is_auction, ErrorCode::NotAuction);
-        require!(Clock::get()?.unix_timestamp >= listing.auction_end, ErrorCode::AuctionNotEnded);
-        require!(listing.highest_bid > 0, ErrorCode::NoBids);
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let now = Clock::get()?.unix_timestamp;
+        require!(listing.is_expired(now), ErrorCode::ListingNotExpired);
+        require!(!listing.is_rented(now), ErrorCode::RentalActive);
         
-        let buyer = listing.highest_bidder.ok_or(ErrorCode::NoBids)?;
+        match (listing.delegated_account, &ctx.accounts.escrow_token_account) {
+            (None, Some(escrow_token_account)) => {
+                // Return escrowed NFTs to seller
+                release_from_vault(
+                    &ctx.accounts.marketplace,
+                    escrow_token_account,
+                    &ctx.accounts.seller_nft_account,
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+                close_escrow(
+                    &ctx.accounts.marketplace,
+                    escrow_token_account,
+                    &ctx.accounts.seller,
+                    &ctx.accounts.token_program,
+                )?;
+            }
+            // Only the seller can reduce the delegation
+            (Some(delegated_account), None) if delegated_account == ctx.accounts.seller_nft_account.key() => {
+                require!(ctx.accounts.seller.is_signer, ErrorCode::DelegatedListing);
+                release_delegation(
+                    &ctx.accounts.marketplace,
+                    &ctx.accounts.seller_nft_account,
+                    &ctx.accounts.nft_mint,
+                    &ctx.accounts.seller,
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+            }
+            _ => return err!(ErrorCode::InvalidEscrowAccount),
+        }
         
-        // Process purchase
-        process_purchase(
-            ctx.accounts.into(),
-            listing.nft_mint,
-            listing.highest_bid,
-            listing.amount,
-            listing.platform_fee,
-        )?;
+        if listing.kind == ListingKind::FixedPrice {
+            ctx.accounts.collection_stats.remove_listing(listing.key());
+        }
         
-        emit!(NftSold {
-            nft_mint: listing.nft_mint,
-            buyer,
-            price: listing.highest_bid,
-            amount: listing.amount,
-        });
+        // Reward the cranker out of the listing rent
+        let listing_info = ctx.accounts.listing.to_account_info();
+        let reward = CRANK_REWARD.min(listing_info.lamports());
+        move_lamports(&listing_info, &ctx.accounts.cranker.to_account_info(), reward)?;
         
-        // Close listing
-        ctx.accounts.listing.close(ctx.accounts.buyer.to_account_info())?;
+        emit!(ListingExpired {
+            listing_id,
+            nft_mint: ctx.accounts.listing.nft_mint,
+            seller: ctx.accounts.listing.seller,
+            cranker: ctx.accounts.cranker.key(),
+            reward,
+        });
         
         Ok(())
     }
 
-    /// Internal function to process purchase and distribute funds
-    fn process_purchase(
-        ctx: PurchaseContext,
-        nft_mint: Pubkey,
-        sale_price: u64,
-        amount: u64,
-        platform_fee: u16,
-    ) -> Result<()> {
-        // Calculate platform fee
-        let fee_amount = sale_price
-            .checked_mul(platform_fee.into())
-            .unwrap()
-            .checked_div(BASIS_POINTS.into())
-            .unwrap();
-        
-        let remaining_amount = sale_price.checked_sub(fee_amount).unwrap();
+    /// Proposes an active fixed-price listing for its collection's floor book
+    ///
+    /// Anyone may call this to add listings that are not in the book, for
+    /// example after many cheaper listings sold or after a price cut. The
+    /// listing enters the book if it is among its cheapest entries.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn refresh_floor(ctx: Context<RefreshFloor>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(listing.kind == ListingKind::FixedPrice, ErrorCode::InvalidListingKind);
+        require!(
+            listing.amount > 0 && !listing.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::NotForSale
+        );
         
-        // Transfer NFT to buyer
-        let cpi_accounts = Transfer {
-            from: ctx.escrow_token_account.to_account_info(),
-            to: ctx.buyer_token_account.to_account_info(),
-            authority: ctx.marketplace.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
-        
-        // Transfer platform fee
-        let cpi_accounts = Transfer {
-            from: ctx.escrow_token_account.to_account_info(),
-            to: ctx.platform_wallet.to_account_info(),
-            authority: ctx.marketplace.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, fee_amount)?;
-        
-        // Transfer remaining to seller
-        let cpi_accounts = Transfer {
-            from: ctx.escrow_token_account.to_account_info(),
-            to: ctx.seller_token_account.to_account_info(),
-            authority: ctx.marketplace.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, remaining_amount)?;
+        ctx.accounts.collection_stats.offer_floor(listing.key(), listing.price);
         
         Ok(())
     }
-}
 
-/// Accounts for initialization
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(init, payer = authority, space = 8 + Marketplace::MAX_SIZE)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    /// Rents a rental listing for a number of days
+    ///
+    /// The NFT stays in escrow and the renter's usage right is recorded on the
+    /// listing. Rent is split between the platform and the owner; creator
+    /// royalties and the secondary fee only apply to sales.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the rental listing
+    /// * days - Number of days to rent for
+    pub fn rent_nft(ctx: Context<RentNft>, listing_id: u64, days: u16) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let max_days = match listing.kind {
+            ListingKind::Rental { max_days } => max_days,
+            _ => return err!(ErrorCode::InvalidListingKind),
+        };
+        require!(days > 0 && days <= max_days, ErrorCode::InvalidRentalTerms);
+        require!(!listing.is_expired(now), ErrorCode::ListingExpired);
+        require!(!listing.is_rented(now), ErrorCode::RentalActive);
+        
+        // Pay the rent
+        let currency = listing.payment_currency;
+        let rent_price = listing.price.checked_mul(days.into()).unwrap();
+        distribute_payment(
+            &PaymentContext {
+                currency,
+                payment_source: payment_account(
+                    currency,
+                    &ctx.accounts.renter_payment_account,
+                    &ctx.accounts.renter.to_account_info(),
+                )?,
+                payment_authority: ctx.accounts.renter.to_account_info(),
+                seller_payment_account: payment_account(
+                    currency,
+                    &ctx.accounts.seller_token_account,
+                    &ctx.accounts.seller,
+                )?,
+                platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                referral: None,
+            },
+            &[],
+            ctx.accounts.marketplace.bump,
+            rent_price,
+            listing.platform_fee,
+            0,
+        )?;
+        
+        // Record the usage right
+        let listing = &mut ctx.accounts.listing;
+        listing.renter = Some(ctx.accounts.renter.key());
+        listing.rented_until = now
+            .checked_add(i64::from(days).checked_mul(SECONDS_PER_DAY).unwrap())
+            .unwrap();
+        
+        emit!(NftRented {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            renter: ctx.accounts.renter.key(),
+            price: rent_price,
+            rented_until: listing.rented_until,
+        });
+        
+        Ok(())
+    }
+
+    /// Ends a rental once its period is over
+    ///
+    /// Anyone may call this after the rental period to clear the renter,
+    /// which makes the listing available to rent again. When the owner signs
+    /// or the listing has expired, the NFT is also returned to the owner and
+    /// the escrow and listing are closed, their rent going to the owner.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the rental listing
+    pub fn reclaim_rental(ctx: Context<ReclaimRental>, listing_id: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &mut ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let renter = listing.renter.ok_or(ErrorCode::NotRented)?;
+        require!(!listing.is_rented(now), ErrorCode::RentalActive);
+        
+        listing.renter = None;
+        listing.rented_until = 0;
+        
+        emit!(RentalReclaimed {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            renter,
+        });
+        
+        // Only the owner or expiry ends the listing itself
+        if ctx.accounts.seller.is_signer || listing.is_expired(now) {
+            release_from_vault(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.seller_nft_account,
+                &ctx.accounts.token_program,
+                listing.amount,
+            )?;
+            close_escrow(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.seller,
+                &ctx.accounts.token_program,
+            )?;
+            ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Purchases an NFT at its fixed price or current Dutch auction price
+    ///
+    /// An optional referral account receives the marketplace's referral share
+    /// of the platform fee.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the listing to purchase from
+    /// * amount - Number of tokens to purchase
+    /// * max_royalty - Highest creator royalty the buyer accepts (basis points)
+    pub fn buy_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyNft<'info>>,
+        listing_id: u64,
+        amount: u64,
+        max_royalty: u16,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(amount > 0 && amount <= listing.amount, ErrorCode::InvalidAmount);
+        
+        let unit_price = listing.purchase_price(Clock::get()?.unix_timestamp)?;
+        let total_price = unit_price.checked_mul(amount).unwrap();
+        let royalties = load_royalties(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.collection_mint,
+            ctx.accounts.edition.as_deref(),
+            ctx.remaining_accounts,
+            listing.payment_currency,
+            listing.payment_mint,
+            max_royalty,
+        )?;
+        
+        if let Some(delegated_token_account) = &ctx.accounts.delegated_token_account {
+            thaw_delegated(
+                &ctx.accounts.marketplace,
+                delegated_token_account,
+                &ctx.accounts.nft_mint,
+                &ctx.accounts.token_program,
+            )?;
+        }
+        
+        // Process payment and distribution
+        process_purchase(
+            PurchaseContext::try_from(&*ctx.accounts)?,
+            &royalties,
+            ctx.accounts.marketplace.bump,
+            total_price,
+            amount,
+            listing.platform_fee,
+            ctx.accounts.marketplace.secondary_fee,
+        )?;
+        ctx.accounts.seller_stats.record_sale(total_price);
+        ctx.accounts.collection_stats.record_sale(total_price, amount)?;
+        
+        // Update listing
+        let listing = &mut ctx.accounts.listing;
+        listing.amount = listing.amount.checked_sub(amount).unwrap();
+        if listing.amount == 0 && listing.kind == ListingKind::FixedPrice {
+            ctx.accounts.collection_stats.remove_listing(listing.key());
+        }
+        
+        emit!(NftSold {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            buyer: ctx.accounts.buyer.key(),
+            price: unit_price,
+            amount,
+        });
+        
+        // Keep tokens still listed from the delegated account frozen
+        if let Some(delegated_token_account) = &ctx.accounts.delegated_token_account {
+            refreeze_delegated(
+                &ctx.accounts.marketplace,
+                &delegated_token_account.to_account_info(),
+                &ctx.accounts.nft_mint,
+                &ctx.accounts.token_program,
+            )?;
+        }
+        
+        if listing.amount == 0 {
+            // Close escrow and listing if all items sold
+            if let Some(escrow_token_account) = &ctx.accounts.escrow_token_account {
+                close_escrow(
+                    &ctx.accounts.marketplace,
+                    escrow_token_account,
+                    &ctx.accounts.seller,
+                    &ctx.accounts.token_program,
+                )?;
+            }
+            ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Buys several fixed-price or Dutch listings in one transaction
+    ///
+    /// Each purchase consumes `account_count` remaining accounts: the listing,
+    /// its escrow (the seller's delegated token account for escrow-less
+    /// listings), the buyer's NFT account, the seller, the seller's payment
+    /// account (the seller wallet for SOL listings), the NFT mint, the
+    /// seller's stats account, the collection stats account and the mint's
+    /// collection record address, followed by the edition and royalty
+    /// accounts when the mint is registered to an edition. Every
+    /// listing must be paid in `payment_mint`, and the whole batch fails if any
+    /// purchase fails or the total exceeds `max_total_price`.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * purchases - Listings to buy, in remaining account order
+    /// * max_total_price - Highest total the buyer accepts to pay
+    /// * max_royalty - Highest creator royalty the buyer accepts (basis points)
+    pub fn buy_listings<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyListings<'info>>,
+        purchases: Vec<BatchPurchase>,
+        max_total_price: u64,
+        max_royalty: u16,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let marketplace = &ctx.accounts.marketplace;
+        let buyer = ctx.accounts.buyer.to_account_info();
+        let mut remaining_accounts = ctx.remaining_accounts;
+        let mut total_price: u64 = 0;
+        
+        for purchase in purchases {
+            let account_count = usize::from(purchase.account_count);
+            require!(
+                account_count >= BATCH_PURCHASE_ACCOUNTS && account_count <= remaining_accounts.len(),
+                ErrorCode::InvalidBatchAccounts
+            );
+            let (accounts, rest) = remaining_accounts.split_at(account_count);
+            remaining_accounts = rest;
+            
+            let mut listing = Account::<Listing>::try_from(&accounts[0])?;
+            require!(listing.id == purchase.listing_id, ErrorCode::ListingIdMismatch);
+            require!(
+                listing.payment_mint == ctx.accounts.payment_mint.key(),
+                ErrorCode::InvalidPaymentCurrency
+            );
+            require!(
+                purchase.amount > 0 && purchase.amount <= listing.amount,
+                ErrorCode::InvalidAmount
+            );
+            let unit_price = listing.purchase_price(now)?;
+            let price = unit_price.checked_mul(purchase.amount).unwrap();
+            total_price = total_price.checked_add(price).unwrap();
+            
+            // Validate the listed tokens, buyer, seller and mint accounts
+            let source_key = match listing.delegated_account {
+                Some(delegated_account) => delegated_account,
+                None => Pubkey::create_program_address(
+                    &[b"escrow", accounts[0].key.as_ref(), &[listing.escrow_bump]],
+                    &crate::ID,
+                )
+                .map_err(|_| error!(ErrorCode::InvalidBatchAccounts))?,
+            };
+            require!(accounts[1].key() == source_key, ErrorCode::InvalidBatchAccounts);
+            let buyer_token_account = Account::<TokenAccount>::try_from(&accounts[2])?;
+            require!(
+                buyer_token_account.mint == listing.nft_mint,
+                ErrorCode::InvalidBatchAccounts
+            );
+            require!(accounts[3].key() == listing.seller, ErrorCode::InvalidBatchAccounts);
+            match listing.payment_currency {
+                PaymentCurrency::Spl => {
+                    let seller_payment_account = Account::<TokenAccount>::try_from(&accounts[4])?;
+                    require!(
+                        seller_payment_account.owner == listing.seller
+                            && seller_payment_account.mint == listing.payment_mint,
+                        ErrorCode::InvalidBatchAccounts
+                    );
+                }
+                PaymentCurrency::Sol => {
+                    require!(accounts[4].key() == listing.seller, ErrorCode::InvalidBatchAccounts);
+                }
+            }
+            let nft_mint = Account::<Mint>::try_from(&accounts[5])?;
+            require!(nft_mint.key() == listing.nft_mint, ErrorCode::InvalidBatchAccounts);
+            let mut seller_stats = Account::<SellerStats>::try_from(&accounts[6])?;
+            let seller_stats_key = Pubkey::create_program_address(
+                &[
+                    b"seller_stats",
+                    listing.seller.as_ref(),
+                    listing.payment_mint.as_ref(),
+                    &[seller_stats.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidBatchAccounts))?;
+            require!(accounts[6].key() == seller_stats_key, ErrorCode::InvalidBatchAccounts);
+            let (collection_mint_key, _) =
+                Pubkey::find_program_address(&[b"collection_mint", nft_mint.key().as_ref()], &crate::ID);
+            require!(accounts[8].key() == collection_mint_key, ErrorCode::InvalidBatchAccounts);
+            let mut collection_stats = Account::<CollectionStats>::try_from(&accounts[7])?;
+            let collection_stats_key = Pubkey::create_program_address(
+                &[
+                    b"collection_stats",
+                    listing.collection.as_ref(),
+                    listing.payment_mint.as_ref(),
+                    &[collection_stats.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidBatchAccounts))?;
+            require!(accounts[7].key() == collection_stats_key, ErrorCode::InvalidBatchAccounts);
+            if listing.delegated_account.is_some() {
+                let delegated_token_account = Account::<TokenAccount>::try_from(&accounts[1])?;
+                thaw_delegated(marketplace, &delegated_token_account, &nft_mint, &ctx.accounts.token_program)?;
+            }
+            require!(
+                marketplace.is_treasury_wallet(listing.payment_currency, &ctx.accounts.platform_wallet),
+                ErrorCode::InvalidTreasury
+            );
+            
+            let currency = listing.payment_currency;
+            let royalties = load_royalties(
+                marketplace,
+                &accounts[8],
+                accounts.get(BATCH_PURCHASE_ACCOUNTS),
+                accounts.get(BATCH_PURCHASE_ACCOUNTS + 1..).unwrap_or_default(),
+                currency,
+                listing.payment_mint,
+                max_royalty,
+            )?;
+            
+            process_purchase(
+                PurchaseContext {
+                    nft_source: accounts[1].clone(),
+                    nft_authority: marketplace.to_account_info(),
+                    buyer_token_account: accounts[2].clone(),
+                    payment: PaymentContext {
+                        currency,
+                        payment_source: payment_account(
+                            currency,
+                            &ctx.accounts.buyer_payment_account,
+                            &buyer,
+                        )?,
+                        payment_authority: buyer.clone(),
+                        seller_payment_account: accounts[4].clone(),
+                        platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        referral: None,
+                    },
+                },
+                &royalties,
+                marketplace.bump,
+                price,
+                purchase.amount,
+                listing.platform_fee,
+                marketplace.secondary_fee,
+            )?;
+            seller_stats.record_sale(price);
+            seller_stats.exit(&crate::ID)?;
+            collection_stats.record_sale(price, purchase.amount)?;
+            
+            // Update listing
+            listing.amount = listing.amount.checked_sub(purchase.amount).unwrap();
+            if listing.amount == 0 && listing.kind == ListingKind::FixedPrice {
+                collection_stats.remove_listing(listing.key());
+            }
+            collection_stats.exit(&crate::ID)?;
+            
+            emit!(NftSold {
+                listing_id: listing.id,
+                nft_mint: listing.nft_mint,
+                buyer: buyer.key(),
+                price: unit_price,
+                amount: purchase.amount,
+            });
+            
+            // Keep tokens still listed from the delegated account frozen
+            if listing.delegated_account.is_some() {
+                refreeze_delegated(marketplace, &accounts[1], &nft_mint, &ctx.accounts.token_program)?;
+            }
+            
+            if listing.amount == 0 {
+                // Close escrow and listing if all items sold
+                if listing.delegated_account.is_none() {
+                    let escrow = Account::<TokenAccount>::try_from(&accounts[1])?;
+                    close_escrow(marketplace, &escrow, &accounts[3], &ctx.accounts.token_program)?;
+                }
+                listing.close(accounts[3].clone())?;
+            } else {
+                listing.exit(&crate::ID)?;
+            }
+        }
+        
+        require!(remaining_accounts.is_empty(), ErrorCode::InvalidBatchAccounts);
+        require!(total_price <= max_total_price, ErrorCode::PriceExceedsMax);
+        
+        Ok(())
+    }
+
+    /// Places a bid in an active auction
+    ///
+    /// The first bid must meet the starting price and later bids must exceed
+    /// the highest bid by the listing's minimum increment. Bids landing inside
+    /// the soft-close window extend the auction. The outbid amount is credited
+    /// to the previous bidder's refund record, to be claimed with `withdraw_refund`.
+    /// An optional referral account is stored with the bid and receives its
+    /// share of the platform fee if the bid wins.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    /// * bid_amount - Bid amount
+    pub fn place_bid(
+        ctx: Context<PlaceBid>,
+        listing_id: u64,
+        bid_amount: u64,
+    ) -> Result<()> {
+        let listing_key = ctx.accounts.listing.key();
+        let bidder = ctx.accounts.bidder.key();
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &mut ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.kind == ListingKind::EnglishAuction, ErrorCode::NotAuction);
+        require!(now < listing.auction_end, ErrorCode::AuctionEnded);
+        
+        // Enforce starting price and minimum increment
+        let min_bid = if listing.highest_bidder.is_some() {
+            listing.min_bid_increment.min_next_bid(listing.highest_bid)
+        } else {
+            listing.price.max(1)
+        };
+        require!(bid_amount >= min_bid, ErrorCode::BidTooLow);
+        
+        // Initialize the bidder's refund record
+        let bid_refund = &mut ctx.accounts.bid_refund;
+        bid_refund.listing = listing_key;
+        bid_refund.bidder = bidder;
+        bid_refund.seller = listing.seller;
+        bid_refund.bump = *ctx.bumps.get("bid_refund").unwrap();
+        
+        // Credit previous bid to the outbid bidder's refund record
+        if let Some(prev_bidder) = listing.highest_bidder {
+            let prev_bid = listing.highest_bid;
+            if prev_bidder == bidder {
+                bid_refund.amount = bid_refund.amount.checked_add(prev_bid).unwrap();
+            } else {
+                let prev_bid_refund = ctx
+                    .accounts
+                    .prev_bid_refund
+                    .as_mut()
+                    .ok_or(ErrorCode::InvalidRefundAccount)?;
+                prev_bid_refund.amount = prev_bid_refund.amount.checked_add(prev_bid).unwrap();
+            }
+            
+            emit!(BidOutbid {
+                listing_id,
+                bidder: prev_bidder,
+                amount: prev_bid,
+            });
+        }
+        
+        // Record new bid
+        listing.highest_bid = bid_amount;
+        listing.highest_bidder = Some(bidder);
+        listing.bid_referral = match &ctx.accounts.referral {
+            Some(referral) => {
+                require!(
+                    referral.payment_mint == listing.payment_mint
+                        && referral.payment_currency == listing.payment_currency
+                        && referral.referrer != bidder,
+                    ErrorCode::InvalidReferralAccount
+                );
+                Some(referral.key())
+            }
+            None => None,
+        };
+        listing.bid_count = listing.bid_count.checked_add(1).unwrap();
+        
+        // Extend the auction if the bid lands inside the soft-close window
+        if listing.soft_close_window > 0 && listing.auction_end - now < listing.soft_close_window {
+            listing.auction_end = now
+                .checked_add(listing.soft_close_window)
+                .ok_or(ErrorCode::InvalidAuctionDuration)?;
+            
+            emit!(AuctionExtended {
+                listing_id,
+                auction_end: listing.auction_end,
+            });
+        }
+        
+        // Transfer bid amount to payment vault
+        deposit_payment(
+            listing.payment_currency,
+            &ctx.accounts.bidder,
+            &ctx.accounts.bidder_token_account,
+            payment_vault_account(
+                listing.payment_currency,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+            )?,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+            bid_amount,
+        )?;
+        
+        emit!(BidPlaced {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            bidder: ctx.accounts.bidder.key(),
+            amount: bid_amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Finalizes an ended auction
+    ///
+    /// Anyone may finalize: the NFT goes to the recorded highest bidder and the
+    /// proceeds and all rent to the recorded seller. If there are no bids or the reserve price was not met, the NFT is
+    /// returned to the seller and the highest bid is credited to the bidder's
+    /// refund record. A hidden reserve can only be revealed within
+    /// `RESERVE_REVEAL_PERIOD` after the auction ends, so an auction with bids
+    /// is finalized after that period and an unrevealed reserve is forfeited. The
+    /// referral account stored with the winning bid, if any, must be passed and
+    /// receives its share of the platform fee.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    pub fn finalize_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalizeAuction<'info>>,
+        listing_id: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.kind == ListingKind::EnglishAuction, ErrorCode::NotAuction);
+        require!(now >= listing.auction_end, ErrorCode::AuctionNotEnded);
+        
+        let reserve_met = match listing.reserve_price {
+            None => true,
+            Some(ReservePrice::Public(reserve)) => listing.highest_bid >= reserve,
+            Some(ReservePrice::Hidden(_)) => {
+                require!(
+                    listing.highest_bidder.is_none()
+                        || now >= listing.auction_end.checked_add(RESERVE_REVEAL_PERIOD).unwrap(),
+                    ErrorCode::ReserveNotRevealed
+                );
+                true
+            }
+        };
+        
+        match listing.highest_bidder {
+            Some(buyer) if reserve_met => {
+                // Bidders accepted the edition's royalties when bidding
+                let royalties = load_royalties(
+                    &ctx.accounts.marketplace,
+                    &ctx.accounts.collection_mint,
+                    ctx.accounts.edition.as_deref(),
+                    ctx.remaining_accounts,
+                    listing.payment_currency,
+                    listing.payment_mint,
+                    BASIS_POINTS,
+                )?;
+                
+                // Process purchase
+                process_purchase(
+                    PurchaseContext::try_from(&*ctx.accounts)?,
+                    &royalties,
+                    ctx.accounts.marketplace.bump,
+                    listing.highest_bid,
+                    listing.amount,
+                    listing.platform_fee,
+                    ctx.accounts.marketplace.secondary_fee,
+                )?;
+                ctx.accounts.seller_stats.record_sale(listing.highest_bid);
+                ctx.accounts
+                    .collection_stats
+                    .record_sale(listing.highest_bid, listing.amount)?;
+                
+                emit!(NftSold {
+                    listing_id,
+                    nft_mint: listing.nft_mint,
+                    buyer,
+                    price: listing.highest_bid,
+                    amount: listing.amount,
+                });
+            }
+            _ => {
+                // Return the NFT to the seller and refund the highest bid
+                release_from_vault(
+                    &ctx.accounts.marketplace,
+                    &ctx.accounts.escrow_token_account,
+                    &ctx.accounts.seller_nft_account,
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+                
+                if listing.highest_bidder.is_some() {
+                    let highest_bid_refund = ctx
+                        .accounts
+                        .highest_bid_refund
+                        .as_mut()
+                        .ok_or(ErrorCode::InvalidRefundAccount)?;
+                    highest_bid_refund.amount = highest_bid_refund
+                        .amount
+                        .checked_add(listing.highest_bid)
+                        .unwrap();
+                }
+                
+                emit!(AuctionUnsold {
+                    listing_id,
+                    nft_mint: listing.nft_mint,
+                    highest_bid: listing.highest_bid,
+                });
+            }
+        }
+        
+        // Close escrow, payment vault (unless refunds are pending) and listing
+        close_escrow(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        close_payment_vault_if_empty(
+            &ctx.accounts.marketplace,
+            &mut ctx.accounts.payment_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        
+        Ok(())
+    }
+
+    /// Buys an auction outright at its buy-now price, ending it immediately
+    ///
+    /// The current highest bid, if any, is credited to the bidder's refund record.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    /// * max_royalty - Highest creator royalty the buyer accepts (basis points)
+    pub fn buy_now<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyNow<'info>>,
+        listing_id: u64,
+        max_royalty: u16,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.kind == ListingKind::EnglishAuction, ErrorCode::NotAuction);
+        require!(Clock::get()?.unix_timestamp < listing.auction_end, ErrorCode::AuctionEnded);
+        
+        let buy_now_price = listing.buy_now_price.ok_or(ErrorCode::BuyNowUnavailable)?;
+        require!(buy_now_price > listing.highest_bid, ErrorCode::BuyNowUnavailable);
+        let royalties = load_royalties(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.collection_mint,
+            ctx.accounts.edition.as_deref(),
+            ctx.remaining_accounts,
+            listing.payment_currency,
+            listing.payment_mint,
+            max_royalty,
+        )?;
+        
+        // Process payment and distribution
+        process_purchase(
+            PurchaseContext::try_from(&*ctx.accounts)?,
+            &royalties,
+            ctx.accounts.marketplace.bump,
+            buy_now_price,
+            listing.amount,
+            listing.platform_fee,
+            ctx.accounts.marketplace.secondary_fee,
+        )?;
+        ctx.accounts.seller_stats.record_sale(buy_now_price);
+        ctx.accounts.collection_stats.record_sale(buy_now_price, listing.amount)?;
+        
+        // Credit the highest bid to the bidder's refund record
+        if listing.highest_bidder.is_some() {
+            let highest_bid_refund = ctx
+                .accounts
+                .highest_bid_refund
+                .as_mut()
+                .ok_or(ErrorCode::InvalidRefundAccount)?;
+            highest_bid_refund.amount = highest_bid_refund
+                .amount
+                .checked_add(listing.highest_bid)
+                .unwrap();
+        }
+        
+        emit!(NftSold {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            buyer: ctx.accounts.buyer.key(),
+            price: buy_now_price,
+            amount: listing.amount,
+        });
+        
+        // Close escrow, payment vault (unless refunds are pending) and listing
+        close_escrow(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        close_payment_vault_if_empty(
+            &ctx.accounts.marketplace,
+            &mut ctx.accounts.payment_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        
+        Ok(())
+    }
+
+    /// Reveals a hidden reserve price committed at listing time
+    ///
+    /// The reserve can only be revealed during the `RESERVE_REVEAL_PERIOD`
+    /// after the auction ends; a reserve left hidden is forfeited and the
+    /// auction settles with the highest bid.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    /// * reserve_price - Reserve price that was committed
+    /// * salt - Salt used in the commitment
+    pub fn reveal_reserve(
+        ctx: Context<RevealReserve>,
+        listing_id: u64,
+        reserve_price: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &mut ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(
+            now >= listing.auction_end
+                && now < listing.auction_end.checked_add(RESERVE_REVEAL_PERIOD).unwrap(),
+            ErrorCode::NotRevealPhase
+        );
+        
+        let commitment = match listing.reserve_price {
+            Some(ReservePrice::Hidden(commitment)) => commitment,
+            _ => return err!(ErrorCode::NoHiddenReserve),
+        };
+        require!(
+            commitment_hash(reserve_price, &salt) == commitment,
+            ErrorCode::InvalidReserveReveal
+        );
+        
+        listing.reserve_price = Some(ReservePrice::Public(reserve_price));
+        
+        emit!(ReserveRevealed {
+            listing_id,
+            reserve_price,
+        });
+        
+        Ok(())
+    }
+
+    /// Commits a sealed bid with a deposit during the bidding phase
+    ///
+    /// The deposit must cover the hidden bid and may exceed it to mask its
+    /// size. Each bidder commits once per listing.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the sealed-bid listing
+    /// * commitment - keccak(bid_amount_le || salt), see `commitment_hash`
+    /// * deposit - Amount of payment tokens locked with the bid
+    pub fn commit_bid(
+        ctx: Context<CommitBid>,
+        listing_id: u64,
+        commitment: [u8; 32],
+        deposit: u64,
+    ) -> Result<()> {
+        let listing = &mut ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let (bidding_end, _) = listing.kind.sealed_bid_phases()?;
+        require!(Clock::get()?.unix_timestamp < bidding_end, ErrorCode::AuctionEnded);
+        require!(deposit >= listing.price && deposit > 0, ErrorCode::BidTooLow);
+        
+        listing.bid_count = listing.bid_count.checked_add(1).unwrap();
+        
+        let sealed_bid = &mut ctx.accounts.sealed_bid;
+        sealed_bid.listing = listing.key();
+        sealed_bid.bidder = ctx.accounts.bidder.key();
+        sealed_bid.seller = listing.seller;
+        sealed_bid.commitment = commitment;
+        sealed_bid.deposit = deposit;
+        sealed_bid.revealed = false;
+        sealed_bid.bump = *ctx.bumps.get("sealed_bid").unwrap();
+        
+        // Transfer deposit to payment vault
+        deposit_payment(
+            listing.payment_currency,
+            &ctx.accounts.bidder,
+            &ctx.accounts.bidder_token_account,
+            payment_vault_account(
+                listing.payment_currency,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+            )?,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+            deposit,
+        )?;
+        
+        emit!(SealedBidCommitted {
+            listing_id,
+            bidder: sealed_bid.bidder,
+            deposit,
+        });
+        
+        Ok(())
+    }
+
+    /// Reveals a sealed bid during the reveal phase
+    ///
+    /// Tracks the highest and second-highest revealed bids; ties go to the
+    /// earlier reveal.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the sealed-bid listing
+    /// * bid_amount - Bid amount that was committed
+    /// * salt - Salt used in the commitment
+    pub fn reveal_bid(
+        ctx: Context<RevealBid>,
+        listing_id: u64,
+        bid_amount: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &mut ctx.accounts.listing;
+        let sealed_bid = &mut ctx.accounts.sealed_bid;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let (bidding_end, reveal_end) = listing.kind.sealed_bid_phases()?;
+        require!(now >= bidding_end && now < reveal_end, ErrorCode::NotRevealPhase);
+        require!(!sealed_bid.revealed, ErrorCode::BidAlreadyRevealed);
+        require!(
+            commitment_hash(bid_amount, &salt) == sealed_bid.commitment,
+            ErrorCode::InvalidBidReveal
+        );
+        require!(bid_amount <= sealed_bid.deposit, ErrorCode::InsufficientDeposit);
+        require!(bid_amount >= listing.price, ErrorCode::BidTooLow);
+        
+        sealed_bid.revealed = true;
+        
+        if listing.highest_bidder.is_none() || bid_amount > listing.highest_bid {
+            listing.second_highest_bid = listing.highest_bid;
+            listing.highest_bid = bid_amount;
+            listing.highest_bidder = Some(sealed_bid.bidder);
+        } else if bid_amount > listing.second_highest_bid {
+            listing.second_highest_bid = bid_amount;
+        }
+        
+        emit!(SealedBidRevealed {
+            listing_id,
+            bidder: sealed_bid.bidder,
+            amount: bid_amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Settles a sealed-bid auction after the reveal phase
+    ///
+    /// The highest revealed bidder wins and pays the second-highest revealed
+    /// bid, or the listing price if greater. The unused part of the winner's
+    /// deposit and all other deposits are claimed with `withdraw_sealed_deposit`.
+    /// Without revealed bids the NFT is returned to the seller.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the sealed-bid listing
+    pub fn settle_sealed_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleSealedAuction<'info>>,
+        listing_id: u64,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        let (_, reveal_end) = listing.kind.sealed_bid_phases()?;
+        require!(Clock::get()?.unix_timestamp >= reveal_end, ErrorCode::AuctionNotEnded);
+        
+        match listing.highest_bidder {
+            Some(winner) => {
+                let clearing_price = listing.second_highest_bid.max(listing.price);
+                let winner_token_account = ctx
+                    .accounts
+                    .winner_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidBuyerAccount)?;
+                let winner_bid = ctx
+                    .accounts
+                    .winner_bid
+                    .as_mut()
+                    .ok_or(ErrorCode::InvalidBuyerAccount)?;
+                let royalties = load_royalties(
+                    &ctx.accounts.marketplace,
+                    &ctx.accounts.collection_mint,
+                    ctx.accounts.edition.as_deref(),
+                    ctx.remaining_accounts,
+                    listing.payment_currency,
+                    listing.payment_mint,
+                    BASIS_POINTS,
+                )?;
+                
+                let currency = listing.payment_currency;
+                
+                process_purchase(
+                    PurchaseContext {
+                        nft_source: ctx.accounts.escrow_token_account.to_account_info(),
+                        nft_authority: ctx.accounts.marketplace.to_account_info(),
+                        buyer_token_account: winner_token_account.to_account_info(),
+                        payment: PaymentContext {
+                            currency,
+                            payment_source: payment_vault_account(
+                                currency,
+                                &ctx.accounts.payment_vault,
+                                &ctx.accounts.sol_vault,
+                            )?,
+                            payment_authority: ctx.accounts.marketplace.to_account_info(),
+                            seller_payment_account: payment_account(
+                                currency,
+                                &ctx.accounts.seller_token_account,
+                                &ctx.accounts.seller,
+                            )?,
+                            platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                            token_program: ctx.accounts.token_program.to_account_info(),
+                            system_program: ctx.accounts.system_program.to_account_info(),
+                            referral: None,
+                        },
+                    },
+                    &royalties,
+                    ctx.accounts.marketplace.bump,
+                    clearing_price,
+                    listing.amount,
+                    listing.platform_fee,
+                    ctx.accounts.marketplace.secondary_fee,
+                )?;
+                ctx.accounts.seller_stats.record_sale(clearing_price);
+                ctx.accounts
+                    .collection_stats
+                    .record_sale(clearing_price, listing.amount)?;
+                
+                // Leave only the unused part of the winner's deposit refundable
+                winner_bid.deposit = winner_bid.deposit.checked_sub(clearing_price).unwrap();
+                
+                emit!(NftSold {
+                    listing_id,
+                    nft_mint: listing.nft_mint,
+                    buyer: winner,
+                    price: clearing_price,
+                    amount: listing.amount,
+                });
+            }
+            None => {
+                release_from_vault(
+                    &ctx.accounts.marketplace,
+                    &ctx.accounts.escrow_token_account,
+                    &ctx.accounts.seller_nft_account,
+                    &ctx.accounts.token_program,
+                    listing.amount,
+                )?;
+                
+                emit!(AuctionUnsold {
+                    listing_id,
+                    nft_mint: listing.nft_mint,
+                    highest_bid: 0,
+                });
+            }
+        }
+        
+        // Close escrow, payment vault (unless deposits are pending) and listing
+        close_escrow(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        close_payment_vault_if_empty(
+            &ctx.accounts.marketplace,
+            &mut ctx.accounts.payment_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        
+        Ok(())
+    }
+
+    /// Withdraws a sealed-bid deposit
+    ///
+    /// Losing bidders may withdraw once the reveal phase is over; the winner
+    /// withdraws the unused part of their deposit after settlement. Closes the
+    /// bid record, and the payment vault once it is drained.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn withdraw_sealed_deposit(ctx: Context<WithdrawSealedDeposit>) -> Result<()> {
+        let bidder = ctx.accounts.bidder.key();
+        let listing_closed = ctx.accounts.listing.data_is_empty();
+        
+        // Deposits stay locked while the listing may still sell to this bidder
+        if !listing_closed {
+            let listing = Account::<Listing>::try_from(&ctx.accounts.listing)?;
+            let (_, reveal_end) = listing.kind.sealed_bid_phases()?;
+            require!(
+                Clock::get()?.unix_timestamp >= reveal_end && listing.highest_bidder != Some(bidder),
+                ErrorCode::DepositLocked
+            );
+        }
+        
+        let amount = ctx.accounts.sealed_bid.deposit;
+        if amount > 0 {
+            release_payment(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.bidder.to_account_info(),
+                &ctx.accounts.bidder_token_account,
+                &ctx.accounts.token_program,
+                amount,
+            )?;
+            
+            // Close the vault once the listing is gone and no funds remain
+            if listing_closed {
+                close_payment_vault_if_empty(
+                    &ctx.accounts.marketplace,
+                    &mut ctx.accounts.payment_vault,
+                    &ctx.accounts.sol_vault,
+                    &ctx.accounts.seller,
+                    &ctx.accounts.token_program,
+                )?;
+            }
+        }
+        
+        emit!(RefundWithdrawn {
+            listing: ctx.accounts.sealed_bid.listing,
+            bidder,
+            amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Withdraws a refundable balance left by outbid or cancelled bids
+    ///
+    /// Closes the refund record, unless the bidder is still the listing's
+    /// highest bidder: later bids and settlement credit that record. Once the listing is settled and the payment
+    /// vault is drained, the vault is closed as well.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn withdraw_refund(ctx: Context<WithdrawRefund>) -> Result<()> {
+        let amount = ctx.accounts.bid_refund.amount;
+        let bidder = ctx.accounts.bidder.key();
+        let is_highest_bidder = !ctx.accounts.listing.data_is_empty()
+            && Account::<Listing>::try_from(&ctx.accounts.listing)?.highest_bidder == Some(bidder);
+        
+        if amount > 0 {
+            release_payment(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.bidder.to_account_info(),
+                &ctx.accounts.bidder_token_account,
+                &ctx.accounts.token_program,
+                amount,
+            )?;
+            
+            // Close the vault once the listing is gone and no funds remain
+            if ctx.accounts.listing.data_is_empty() {
+                close_payment_vault_if_empty(
+                    &ctx.accounts.marketplace,
+                    &mut ctx.accounts.payment_vault,
+                    &ctx.accounts.sol_vault,
+                    &ctx.accounts.seller,
+                    &ctx.accounts.token_program,
+                )?;
+            }
+        }
+        
+        emit!(RefundWithdrawn {
+            listing: ctx.accounts.bid_refund.listing,
+            bidder,
+            amount,
+        });
+        
+        if is_highest_bidder {
+            ctx.accounts.bid_refund.amount = 0;
+        } else {
+            ctx.accounts.bid_refund.close(ctx.accounts.bidder.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Places a quantity bid in a uniform-price auction
+    ///
+    /// The full `quantity * unit_price` is deposited; the part not used at
+    /// the clearing price is refunded when the bid is claimed. Each bidder
+    /// has a single bid per auction.
+    ///
+    /// Once the auction holds `MAX_UNIT_BIDS` bids, a new bid must beat the
+    /// lowest one, which leaves the auction: the remaining accounts then hold
+    /// every bid of the auction, ordered by address, and the lowest bid's
+    /// deposit is credited to its bidder's refund record, to be claimed with
+    /// `withdraw_refund`.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    /// * quantity - Number of units wanted
+    /// * unit_price - Highest price per unit the bidder pays
+    pub fn place_unit_bid<'info>(
+        ctx: Context<'_, '_, '_, 'info, PlaceUnitBid<'info>>,
+        listing_id: u64,
+        quantity: u64,
+        unit_price: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let listing = &mut ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.kind == ListingKind::UniformPriceAuction, ErrorCode::NotAuction);
+        require!(now < listing.auction_end, ErrorCode::AuctionEnded);
+        require!(quantity > 0 && quantity <= listing.amount, ErrorCode::InvalidAmount);
+        require!(unit_price >= listing.price.max(1), ErrorCode::BidTooLow);
+        
+        // Initialize the bidder's refund record
+        let bid_refund = &mut ctx.accounts.bid_refund;
+        bid_refund.listing = listing.key();
+        bid_refund.bidder = ctx.accounts.bidder.key();
+        bid_refund.seller = listing.seller;
+        bid_refund.bump = *ctx.bumps.get("bid_refund").unwrap();
+        
+        // Make room in a full auction by crediting out its lowest bid
+        if listing.bid_count >= MAX_UNIT_BIDS {
+            require!(
+                ctx.remaining_accounts.len() == listing.bid_count as usize,
+                ErrorCode::InvalidBidAccounts
+            );
+            require!(
+                ctx.remaining_accounts.windows(2).all(|pair| pair[0].key < pair[1].key),
+                ErrorCode::InvalidBidAccounts
+            );
+            let bids = ctx
+                .remaining_accounts
+                .iter()
+                .map(|info| {
+                    let bid = Account::<UnitBid>::try_from(info)?;
+                    require!(bid.listing == listing.key(), ErrorCode::InvalidBidAccounts);
+                    Ok(bid)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Settlement ranks later bids and higher addresses last on equal prices
+            let lowest = bids
+                .iter()
+                .min_by(|a, b| {
+                    a.unit_price
+                        .cmp(&b.unit_price)
+                        .then(b.placed_at.cmp(&a.placed_at))
+                        .then(b.key().cmp(&a.key()))
+                })
+                .unwrap();
+            require!(unit_price > lowest.unit_price, ErrorCode::BidTooLow);
+            
+            let outbid = ctx.accounts.outbid.as_ref().ok_or(ErrorCode::InvalidBidAccounts)?;
+            require!(outbid.key() == lowest.key(), ErrorCode::InvalidBidAccounts);
+            let outbid_refund = ctx
+                .accounts
+                .outbid_refund
+                .as_mut()
+                .ok_or(ErrorCode::InvalidRefundAccount)?;
+            require!(outbid_refund.bidder == outbid.bidder, ErrorCode::InvalidRefundAccount);
+            outbid_refund.amount = outbid_refund.amount.checked_add(outbid.deposit).unwrap();
+            
+            emit!(BidOutbid {
+                listing_id,
+                bidder: outbid.bidder,
+                amount: outbid.deposit,
+            });
+            
+            // The bid's rent is returned along with the refund record
+            outbid.close(outbid_refund.to_account_info())?;
+            listing.bid_count = listing.bid_count.checked_sub(1).unwrap();
+        }
+        
+        let deposit = quantity.checked_mul(unit_price).unwrap();
+        let unit_bid = &mut ctx.accounts.unit_bid;
+        unit_bid.listing = listing.key();
+        unit_bid.bidder = ctx.accounts.bidder.key();
+        unit_bid.quantity = quantity;
+        unit_bid.unit_price = unit_price;
+        unit_bid.deposit = deposit;
+        unit_bid.filled = 0;
+        unit_bid.placed_at = now;
+        unit_bid.bump = *ctx.bumps.get("unit_bid").unwrap();
+        
+        listing.bid_count = listing.bid_count.checked_add(1).unwrap();
+        
+        deposit_payment(
+            listing.payment_currency,
+            &ctx.accounts.bidder,
+            &ctx.accounts.bidder_token_account,
+            payment_vault_account(
+                listing.payment_currency,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+            )?,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+            deposit,
+        )?;
+        
+        emit!(UnitBidPlaced {
+            listing_id,
+            bidder: unit_bid.bidder,
+            quantity,
+            unit_price,
+        });
+        
+        Ok(())
+    }
+
+    /// Settles an ended uniform-price auction
+    ///
+    /// The remaining accounts hold every bid of the auction, ordered by
+    /// address, followed by the royalty accounts. Units are allocated to the
+    /// highest unit prices first (earlier bids win ties) and all winners pay
+    /// the lowest winning unit price. The seller is paid for the units sold
+    /// and unsold units are returned; bidders then collect their units and
+    /// refunds with `claim_unit_bid`.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * listing_id - ID of the auction listing
+    pub fn settle_unit_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleUnitAuction<'info>>,
+        listing_id: u64,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        require!(listing.id == listing_id, ErrorCode::ListingIdMismatch);
+        require!(listing.kind == ListingKind::UniformPriceAuction, ErrorCode::NotAuction);
+        require!(listing.clearing_price.is_none(), ErrorCode::AuctionSettled);
+        require!(Clock::get()?.unix_timestamp >= listing.auction_end, ErrorCode::AuctionNotEnded);
+        
+        // Load every bid exactly once
+        let bid_count = listing.bid_count as usize;
+        require!(ctx.remaining_accounts.len() >= bid_count, ErrorCode::InvalidBidAccounts);
+        let (bid_accounts, royalty_accounts) = ctx.remaining_accounts.split_at(bid_count);
+        require!(
+            bid_accounts.windows(2).all(|pair| pair[0].key < pair[1].key),
+            ErrorCode::InvalidBidAccounts
+        );
+        let mut bids = bid_accounts
+            .iter()
+            .map(|info| {
+                let bid = Account::<UnitBid>::try_from(info)?;
+                require!(bid.listing == listing.key(), ErrorCode::InvalidBidAccounts);
+                Ok(bid)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        
+        // Allocate units to the best bids
+        bids.sort_by(|a, b| {
+            b.unit_price
+                .cmp(&a.unit_price)
+                .then(a.placed_at.cmp(&b.placed_at))
+                .then(a.key().cmp(&b.key()))
+        });
+        let mut units_left = listing.amount;
+        let mut clearing_price = 0;
+        for bid in bids.iter_mut() {
+            bid.filled = bid.quantity.min(units_left);
+            if bid.filled > 0 {
+                units_left -= bid.filled;
+                clearing_price = bid.unit_price;
+            }
+            bid.exit(&crate::ID)?;
+        }
+        let units_sold = listing.amount - units_left;
+        
+        // Pay the seller for the units sold
+        if units_sold > 0 {
+            let currency = listing.payment_currency;
+            let sale_price = clearing_price.checked_mul(units_sold).unwrap();
+            let royalties = load_royalties(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.collection_mint,
+                ctx.accounts.edition.as_deref(),
+                royalty_accounts,
+                currency,
+                listing.payment_mint,
+                BASIS_POINTS,
+            )?;
+            distribute_payment(
+                &PaymentContext {
+                    currency,
+                    payment_source: payment_vault_account(
+                        currency,
+                        &ctx.accounts.payment_vault,
+                        &ctx.accounts.sol_vault,
+                    )?,
+                    payment_authority: ctx.accounts.marketplace.to_account_info(),
+                    seller_payment_account: payment_account(
+                        currency,
+                        &ctx.accounts.seller_token_account,
+                        &ctx.accounts.seller,
+                    )?,
+                    platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    referral: None,
+                },
+                &royalties,
+                ctx.accounts.marketplace.bump,
+                sale_price,
+                listing.platform_fee,
+                ctx.accounts.marketplace.secondary_fee,
+            )?;
+            ctx.accounts.seller_stats.record_sale(sale_price);
+            ctx.accounts.collection_stats.record_sale(sale_price, units_sold)?;
+        }
+        
+        // Return unsold units to the seller
+        if units_left > 0 {
+            release_from_vault(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.seller_nft_account,
+                &ctx.accounts.token_program,
+                units_left,
+            )?;
+        }
+        
+        emit!(UnitAuctionSettled {
+            listing_id,
+            nft_mint: listing.nft_mint,
+            clearing_price,
+            units_sold,
+        });
+        
+        // Keep units in escrow for the winners to claim
+        let listing = &mut ctx.accounts.listing;
+        listing.amount = units_sold;
+        listing.clearing_price = Some(clearing_price);
+        
+        if listing.bid_count == 0 {
+            close_escrow(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.seller,
+                &ctx.accounts.token_program,
+            )?;
+            close_payment_vault_if_empty(
+                &ctx.accounts.marketplace,
+                &mut ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.seller,
+                &ctx.accounts.token_program,
+            )?;
+            ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Claims the units won by a bid in a settled uniform-price auction
+    ///
+    /// Transfers the filled units and refunds the deposit above their cost at
+    /// the clearing price. The last claim closes the escrow, payment vault and
+    /// listing.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn claim_unit_bid(ctx: Context<ClaimUnitBid>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let clearing_price = listing.clearing_price.ok_or(ErrorCode::AuctionNotSettled)?;
+        let unit_bid = &ctx.accounts.unit_bid;
+        let cost = clearing_price.checked_mul(unit_bid.filled).unwrap();
+        let refund = unit_bid.deposit.checked_sub(cost).unwrap();
+        
+        if unit_bid.filled > 0 {
+            let bidder_nft_account = ctx
+                .accounts
+                .bidder_nft_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidBuyerAccount)?;
+            release_from_vault(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                bidder_nft_account,
+                &ctx.accounts.token_program,
+                unit_bid.filled,
+            )?;
+        }
+        if refund > 0 {
+            release_payment(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.bidder.to_account_info(),
+                &ctx.accounts.bidder_token_account,
+                &ctx.accounts.token_program,
+                refund,
+            )?;
+        }
+        
+        emit!(UnitBidClaimed {
+            listing_id: listing.id,
+            bidder: unit_bid.bidder,
+            units: unit_bid.filled,
+            price: clearing_price,
+            refund,
+        });
+        
+        let listing = &mut ctx.accounts.listing;
+        listing.amount = listing.amount.checked_sub(unit_bid.filled).unwrap();
+        listing.bid_count = listing.bid_count.checked_sub(1).unwrap();
+        
+        if listing.bid_count == 0 {
+            close_escrow(
+                &ctx.accounts.marketplace,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.seller,
+                &ctx.accounts.token_program,
+            )?;
+            close_payment_vault_if_empty(
+                &ctx.accounts.marketplace,
+                &mut ctx.accounts.payment_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.seller,
+                &ctx.accounts.token_program,
+            )?;
+            ctx.accounts.listing.close(ctx.accounts.seller.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Lists several tokens for sale together at a single price
+    ///
+    /// The remaining accounts hold, for each item in order, the token mint,
+    /// the seller's token account, the uninitialized bundle escrow
+    /// `[b"bundle_escrow", bundle, mint]`, which is created here, the
+    /// addresses of the mint's collection record and its collection's fee
+    /// override, and the allowlist entry covering the mint. The platform fee
+    /// is the highest fee any item would list at on its own.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * nonce - Seller-chosen nonce used to derive the bundle address
+    /// * price - Price of the whole bundle
+    /// * amounts - Number of tokens of each mint, in remaining account order
+    /// * payment_currency - Whether buyers pay in native SOL or in `payment_mint` tokens
+    pub fn list_bundle<'info>(
+        ctx: Context<'_, '_, '_, 'info, ListBundle<'info>>,
+        nonce: u64,
+        price: u64,
+        amounts: Vec<u64>,
+        payment_currency: PaymentCurrency,
+    ) -> Result<()> {
+        require!(price > 0, ErrorCode::InvalidAmount);
+        require!(
+            !amounts.is_empty() && amounts.len() <= MAX_BUNDLE_ITEMS,
+            ErrorCode::InvalidBundleSize
+        );
+        require!(
+            ctx.remaining_accounts.len() == amounts.len() * BUNDLE_LISTING_ACCOUNTS,
+            ErrorCode::InvalidBundleAccounts
+        );
+        require!(
+            payment_currency == PaymentCurrency::Spl
+                || ctx.accounts.payment_mint.key() == native_mint::ID,
+            ErrorCode::InvalidPaymentCurrency
+        );
+        
+        let bundle_key = ctx.accounts.bundle.key();
+        let rent = Rent::get()?;
+        let mut items = Vec::with_capacity(amounts.len());
+        let mut collection_fees = Vec::with_capacity(amounts.len());
+        for (accounts, &amount) in ctx
+            .remaining_accounts
+            .chunks(BUNDLE_LISTING_ACCOUNTS)
+            .zip(amounts.iter())
+        {
+            let (mint, seller_token_account, escrow) = (&accounts[0], &accounts[1], &accounts[2]);
+            let (collection_mint, collection_fee) = (&accounts[3], &accounts[4]);
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            Account::<Mint>::try_from(mint)?;
+            let source = Account::<TokenAccount>::try_from(seller_token_account)?;
+            require!(
+                source.mint == mint.key() && source.owner == ctx.accounts.seller.key(),
+                ErrorCode::InvalidBundleAccounts
+            );
+            
+            // Create the item escrow owned by the marketplace PDA
+            let (escrow_key, escrow_bump) = Pubkey::find_program_address(
+                &[b"bundle_escrow", bundle_key.as_ref(), mint.key.as_ref()],
+                ctx.program_id,
+            );
+            require!(escrow.key() == escrow_key, ErrorCode::InvalidBundleAccounts);
+            let escrow_seeds: &[&[u8]] = &[
+                b"bundle_escrow",
+                bundle_key.as_ref(),
+                mint.key.as_ref(),
+                &[escrow_bump],
+            ];
+            let escrow_signer = &[escrow_seeds];
+            let cpi_accounts = CreateAccount {
+                from: ctx.accounts.seller.to_account_info(),
+                to: escrow.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                cpi_accounts,
+                escrow_signer,
+            );
+            system_program::create_account(
+                cpi_ctx,
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &token::ID,
+            )?;
+            let cpi_accounts = InitializeAccount3 {
+                account: escrow.clone(),
+                mint: mint.clone(),
+                authority: ctx.accounts.marketplace.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token::initialize_account3(cpi_ctx)?;
+            
+            // Transfer the item to escrow
+            let cpi_accounts = Transfer {
+                from: seller_token_account.clone(),
+                to: escrow.clone(),
+                authority: ctx.accounts.seller.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token::transfer(cpi_ctx, amount)?;
+            
+            // Look up the fee override of the item's collection
+            let (collection_mint_key, _) =
+                Pubkey::find_program_address(&[b"collection_mint", mint.key.as_ref()], ctx.program_id);
+            require!(collection_mint.key() == collection_mint_key, ErrorCode::InvalidBundleAccounts);
+            let registered = registered_collection(collection_mint);
+            let collection = registered.unwrap_or(mint.key());
+            let (collection_fee_key, _) =
+                Pubkey::find_program_address(&[b"collection_fee", collection.as_ref()], ctx.program_id);
+            require!(collection_fee.key() == collection_fee_key, ErrorCode::InvalidBundleAccounts);
+            collection_fees.push(read_record::<CollectionFee>(collection_fee));
+            let allowlist_entry = Account::<AllowlistEntry>::try_from(&accounts[5])?;
+            require!(
+                allowlist_entry.allows(&ctx.accounts.marketplace, mint.key, registered),
+                ErrorCode::AssetNotAllowed
+            );
+            
+            items.push(BundleItem {
+                mint: mint.key(),
+                amount,
+                escrow_bump,
+            });
+        }
+        
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.seller = ctx.accounts.seller.key();
+        seller_stats.payment_mint = ctx.accounts.payment_mint.key();
+        seller_stats.bump = *ctx.bumps.get("seller_stats").unwrap();
+        
+        let marketplace = &mut ctx.accounts.marketplace;
+        let bundle = &mut ctx.accounts.bundle;
+        bundle.id = marketplace.listing_count;
+        marketplace.listing_count = marketplace.listing_count.checked_add(1).unwrap();
+        bundle.nonce = nonce;
+        bundle.bump = *ctx.bumps.get("bundle").unwrap();
+        bundle.seller = ctx.accounts.seller.key();
+        bundle.payment_mint = ctx.accounts.payment_mint.key();
+        bundle.payment_currency = payment_currency;
+        bundle.price = price;
+        let fee_schedule = read_record::<FeeSchedule>(&ctx.accounts.fee_schedule);
+        bundle.platform_fee = collection_fees
+            .iter()
+            .map(|collection_fee| {
+                marketplace.listing_fee(collection_fee.as_ref(), fee_schedule.as_ref(), seller_stats.volume)
+            })
+            .max()
+            .unwrap();
+        bundle.items = items;
+        
+        emit!(BundleListed {
+            bundle_id: bundle.id,
+            seller: bundle.seller,
+            price,
+            mints: bundle.items.iter().map(|item| item.mint).collect(),
+        });
+        
+        Ok(())
+    }
+
+    /// Buys every item of a bundle in one transaction
+    ///
+    /// The remaining accounts hold, for each bundle item in order, its escrow
+    /// and the buyer's token account for that mint, followed by the sale
+    /// accounts of each item: the mint's collection record address, its
+    /// collection stats account and, for mints registered to an edition, the
+    /// edition and royalty accounts. The price is attributed to items by token
+    /// count; each item pays its edition's royalties and counts towards its
+    /// collection stats on its share.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * bundle_id - ID of the bundle to purchase
+    /// * item_account_counts - Number of sale accounts of each item
+    /// * max_royalty - Highest creator royalty the buyer accepts (basis points)
+    pub fn buy_bundle<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyBundle<'info>>,
+        bundle_id: u64,
+        item_account_counts: Vec<u8>,
+        max_royalty: u16,
+    ) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        require!(bundle.id == bundle_id, ErrorCode::ListingIdMismatch);
+        require!(
+            item_account_counts.len() == bundle.items.len()
+                && ctx.remaining_accounts.len() >= bundle.items.len() * 2,
+            ErrorCode::InvalidBundleAccounts
+        );
+        let currency = bundle.payment_currency;
+        let (release_accounts, mut sale_accounts) = ctx.remaining_accounts.split_at(bundle.items.len() * 2);
+        
+        release_bundle_items(
+            &ctx.accounts.marketplace,
+            bundle,
+            release_accounts,
+            ctx.accounts.buyer.key(),
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+        
+        let total_amount = bundle
+            .items
+            .iter()
+            .try_fold(0u64, |total, item| total.checked_add(item.amount))
+            .unwrap();
+        let mut royalties = Vec::new();
+        for (item, &account_count) in bundle.items.iter().zip(item_account_counts.iter()) {
+            let account_count = usize::from(account_count);
+            require!(
+                account_count >= 2 && account_count <= sale_accounts.len(),
+                ErrorCode::InvalidBundleAccounts
+            );
+            let (accounts, rest) = sale_accounts.split_at(account_count);
+            sale_accounts = rest;
+            
+            let (collection_mint_key, _) =
+                Pubkey::find_program_address(&[b"collection_mint", item.mint.as_ref()], &crate::ID);
+            require!(accounts[0].key() == collection_mint_key, ErrorCode::InvalidBundleAccounts);
+            let item_price = bundle
+                .price
+                .checked_mul(item.amount)
+                .unwrap()
+                .checked_div(total_amount)
+                .unwrap();
+            
+            // Royalties are owed on the item's share of the price
+            let item_royalties = load_royalties(
+                &ctx.accounts.marketplace,
+                &accounts[0],
+                accounts.get(2),
+                accounts.get(3..).unwrap_or_default(),
+                currency,
+                bundle.payment_mint,
+                max_royalty,
+            )?;
+            royalties.extend(item_royalties.into_iter().map(|royalty| RoyaltyPayout {
+                item_price: Some(item_price),
+                ..royalty
+            }));
+            
+            let mut collection_stats = load_collection_stats(
+                &accounts[1],
+                collection_key(&item.mint, &accounts[0]),
+                bundle.payment_mint,
+                &ctx.accounts.buyer,
+                &ctx.accounts.system_program,
+            )?;
+            collection_stats.record_sale(item_price, item.amount)?;
+            collection_stats.exit(&crate::ID)?;
+        }
+        require!(sale_accounts.is_empty(), ErrorCode::InvalidBundleAccounts);
+        
+        distribute_payment(
+            &PaymentContext {
+                currency,
+                payment_source: payment_account(
+                    currency,
+                    &ctx.accounts.buyer_payment_account,
+                    &ctx.accounts.buyer.to_account_info(),
+                )?,
+                payment_authority: ctx.accounts.buyer.to_account_info(),
+                seller_payment_account: payment_account(
+                    currency,
+                    &ctx.accounts.seller_token_account,
+                    &ctx.accounts.seller,
+                )?,
+                platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                referral: None,
+            },
+            &royalties,
+            ctx.accounts.marketplace.bump,
+            bundle.price,
+            bundle.platform_fee,
+            ctx.accounts.marketplace.secondary_fee,
+        )?;
+        ctx.accounts.seller_stats.record_sale(bundle.price);
+        
+        emit!(BundleSold {
+            bundle_id,
+            buyer: ctx.accounts.buyer.key(),
+            price: bundle.price,
+        });
+        
+        Ok(())
+    }
+
+    /// Cancels a bundle, returning every item to the seller
+    ///
+    /// The remaining accounts hold, for each bundle item in order, its escrow
+    /// and the seller's token account for that mint.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * bundle_id - ID of the bundle to cancel
+    pub fn cancel_bundle<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelBundle<'info>>,
+        bundle_id: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.bundle.id == bundle_id, ErrorCode::ListingIdMismatch);
+        
+        release_bundle_items(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.bundle,
+            ctx.remaining_accounts,
+            ctx.accounts.seller.key(),
+            &ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+        
+        emit!(BundleCancelled {
+            bundle_id,
+            seller: ctx.accounts.seller.key(),
+        });
+        
+        Ok(())
+    }
+
+    /// Makes an offer on a specific NFT or any token of a collection
+    ///
+    /// The full offer amount is escrowed in an offer vault until the offer is
+    /// accepted or cancelled. SPL offers pass an `offer_vault`; SOL offers pass
+    /// the native mint as `payment_mint` and a `sol_vault` instead.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * nonce - Buyer-chosen nonce used to derive the offer address
+    /// * target - Mint or collection the offer applies to
+    /// * price - Price offered per token
+    /// * amount - Number of tokens wanted
+    /// * expires_at - Timestamp after which the offer can no longer be accepted
+    /// * max_royalty - Highest creator royalty the buyer accepts (basis points)
+    pub fn make_offer(
+        ctx: Context<MakeOffer>,
+        nonce: u64,
+        target: OfferTarget,
+        price: u64,
+        amount: u64,
+        expires_at: i64,
+        max_royalty: u16,
+    ) -> Result<()> {
+        require!(price > 0 && amount > 0, ErrorCode::InvalidAmount);
+        require!(expires_at > Clock::get()?.unix_timestamp, ErrorCode::InvalidExpiry);
+        require!(
+            ctx.accounts.offer_vault.is_some() != ctx.accounts.sol_vault.is_some(),
+            ErrorCode::InvalidPaymentVault
+        );
+        let payment_currency = if ctx.accounts.sol_vault.is_some() {
+            PaymentCurrency::Sol
+        } else {
+            PaymentCurrency::Spl
+        };
+        require!(
+            payment_currency == PaymentCurrency::Spl || ctx.accounts.payment_mint.key() == native_mint::ID,
+            ErrorCode::InvalidPaymentCurrency
+        );
+        
+        let offer = &mut ctx.accounts.offer;
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.nonce = nonce;
+        offer.bump = *ctx.bumps.get("offer").unwrap();
+        offer.vault_bump = ctx
+            .bumps
+            .get("offer_vault")
+            .or_else(|| ctx.bumps.get("sol_vault"))
+            .copied()
+            .unwrap_or_default();
+        offer.target = target;
+        offer.payment_mint = ctx.accounts.payment_mint.key();
+        offer.payment_currency = payment_currency;
+        offer.price = price;
+        offer.amount = amount;
+        offer.expires_at = expires_at;
+        offer.max_royalty = max_royalty;
+        
+        // Escrow the full offer amount
+        let total_price = price.checked_mul(amount).unwrap();
+        deposit_payment(
+            payment_currency,
+            &ctx.accounts.buyer,
+            &ctx.accounts.buyer_payment_account,
+            payment_vault_account(payment_currency, &ctx.accounts.offer_vault, &ctx.accounts.sol_vault)?,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+            total_price,
+        )?;
+        
+        emit!(OfferMade {
+            offer: offer.key(),
+            buyer: offer.buyer,
+            target,
+            price,
+            amount,
+            expires_at,
+        });
+        
+        Ok(())
+    }
+
+    /// Cancels an offer, returning the escrowed funds to the buyer
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let refund = match (&ctx.accounts.offer_vault, &ctx.accounts.sol_vault) {
+            (Some(offer_vault), _) => offer_vault.amount,
+            (None, Some(sol_vault)) => SolVault::balance(sol_vault)?,
+            (None, None) => return err!(ErrorCode::InvalidPaymentVault),
+        };
+        release_payment(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.token_program,
+            refund,
+        )?;
+        close_payment_vault_if_empty(
+            &ctx.accounts.marketplace,
+            &mut ctx.accounts.offer_vault,
+            &ctx.accounts.sol_vault,
+            &ctx.accounts.buyer.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+        
+        emit!(OfferCancelled {
+            offer: ctx.accounts.offer.key(),
+            buyer: ctx.accounts.buyer.key(),
+        });
+        
+        Ok(())
+    }
+
+    /// Accepts an offer, selling tokens directly from the holder's wallet
+    ///
+    /// Payment is drawn from the offer vault with the same fee distribution as
+    /// listing sales, at the platform fee the seller would list at. The offer
+    /// is closed once fully filled.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * amount - Number of tokens to sell
+    pub fn accept_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        require!(Clock::get()?.unix_timestamp < offer.expires_at, ErrorCode::OfferExpired);
+        require!(amount > 0 && amount <= offer.amount, ErrorCode::InvalidAmount);
+        require!(
+            offer.target.matches(
+                &ctx.accounts.nft_mint.key(),
+                registered_collection(&ctx.accounts.collection_mint),
+            ),
+            ErrorCode::OfferTargetMismatch
+        );
+        
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.seller = ctx.accounts.seller.key();
+        seller_stats.payment_mint = offer.payment_mint;
+        seller_stats.bump = *ctx.bumps.get("seller_stats").unwrap();
+        let platform_fee = ctx.accounts.marketplace.listing_fee(
+            read_record::<CollectionFee>(&ctx.accounts.collection_fee).as_ref(),
+            read_record::<FeeSchedule>(&ctx.accounts.fee_schedule).as_ref(),
+            seller_stats.volume,
+        );
+        
+        let total_price = offer.price.checked_mul(amount).unwrap();
+        let currency = offer.payment_currency;
+        let royalties = load_royalties(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.collection_mint,
+            ctx.accounts.edition.as_deref(),
+            ctx.remaining_accounts,
+            currency,
+            offer.payment_mint,
+            offer.max_royalty,
+        )?;
+        
+        process_purchase(
+            PurchaseContext {
+                nft_source: ctx.accounts.seller_nft_account.to_account_info(),
+                nft_authority: ctx.accounts.seller.to_account_info(),
+                buyer_token_account: ctx.accounts.buyer_nft_account.to_account_info(),
+                payment: PaymentContext {
+                    currency,
+                    payment_source: payment_vault_account(
+                        currency,
+                        &ctx.accounts.offer_vault,
+                        &ctx.accounts.sol_vault,
+                    )?,
+                    payment_authority: ctx.accounts.marketplace.to_account_info(),
+                    seller_payment_account: payment_account(
+                        currency,
+                        &ctx.accounts.seller_payment_account,
+                        &ctx.accounts.seller.to_account_info(),
+                    )?,
+                    platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    referral: None,
+                },
+            },
+            &royalties,
+            ctx.accounts.marketplace.bump,
+            total_price,
+            amount,
+            platform_fee,
+            ctx.accounts.marketplace.secondary_fee,
+        )?;
+        ctx.accounts.seller_stats.record_sale(total_price);
+        
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection = collection_key(&ctx.accounts.nft_mint.key(), &ctx.accounts.collection_mint);
+        collection_stats.payment_mint = ctx.accounts.offer.payment_mint;
+        collection_stats.bump = *ctx.bumps.get("collection_stats").unwrap();
+        collection_stats.record_sale(total_price, amount)?;
+        
+        let offer = &mut ctx.accounts.offer;
+        offer.amount = offer.amount.checked_sub(amount).unwrap();
+        
+        emit!(OfferAccepted {
+            offer: offer.key(),
+            buyer: offer.buyer,
+            seller: ctx.accounts.seller.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            price: offer.price,
+            amount,
+        });
+        
+        if offer.amount == 0 {
+            // Close vault and offer once fully filled
+            close_payment_vault_if_empty(
+                &ctx.accounts.marketplace,
+                &mut ctx.accounts.offer_vault,
+                &ctx.accounts.sol_vault,
+                &ctx.accounts.buyer,
+                &ctx.accounts.token_program,
+            )?;
+            ctx.accounts.offer.close(ctx.accounts.buyer.to_account_info())?;
+        }
+        
+        Ok(())
+    }
+
+    /// Fills an order the seller signed off-chain
+    ///
+    /// The transaction must carry an ed25519 program instruction verifying the
+    /// seller's signature over the order message, directly before this one.
+    /// The NFT is transferred from the seller's wallet using the marketplace
+    /// PDA's delegate approval and paid for like a listing sale, at the
+    /// platform fee the seller would list at.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * order - Order signed by the seller
+    /// * amount - Number of tokens to buy
+    /// * max_royalty - Highest total royalty in basis points the buyer accepts
+    pub fn fill_order<'info>(
+        ctx: Context<'_, '_, '_, 'info, FillOrder<'info>>,
+        order: SignedOrder,
+        amount: u64,
+        max_royalty: u16,
+    ) -> Result<()> {
+        verify_order_signature(&ctx.accounts.instructions, &order)?;
+        require!(
+            order.payment_currency == PaymentCurrency::Spl || order.payment_mint == native_mint::ID,
+            ErrorCode::InvalidPaymentCurrency
+        );
+        require!(Clock::get()?.unix_timestamp < order.expires_at, ErrorCode::OrderExpired);
+        
+        let order_state = &ctx.accounts.order_state;
+        require!(!order_state.cancelled, ErrorCode::OrderCancelled);
+        let filled = order_state.filled.checked_add(amount).unwrap();
+        require!(amount > 0 && filled <= order.amount, ErrorCode::InvalidAmount);
+        
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.seller = order.seller;
+        seller_stats.payment_mint = order.payment_mint;
+        seller_stats.bump = *ctx.bumps.get("seller_stats").unwrap();
+        let platform_fee = ctx.accounts.marketplace.listing_fee(
+            read_record::<CollectionFee>(&ctx.accounts.collection_fee).as_ref(),
+            read_record::<FeeSchedule>(&ctx.accounts.fee_schedule).as_ref(),
+            seller_stats.volume,
+        );
+        
+        let total_price = order.price.checked_mul(amount).unwrap();
+        let royalties = load_royalties(
+            &ctx.accounts.marketplace,
+            &ctx.accounts.collection_mint,
+            ctx.accounts.edition.as_deref(),
+            ctx.remaining_accounts,
+            order.payment_currency,
+            order.payment_mint,
+            max_royalty,
+        )?;
+        
+        let currency = order.payment_currency;
+        process_purchase(
+            PurchaseContext {
+                nft_source: ctx.accounts.seller_nft_account.to_account_info(),
+                nft_authority: ctx.accounts.marketplace.to_account_info(),
+                buyer_token_account: ctx.accounts.buyer_nft_account.to_account_info(),
+                payment: PaymentContext {
+                    currency,
+                    payment_source: payment_account(
+                        currency,
+                        &ctx.accounts.buyer_payment_account,
+                        &ctx.accounts.buyer.to_account_info(),
+                    )?,
+                    payment_authority: ctx.accounts.buyer.to_account_info(),
+                    seller_payment_account: payment_account(
+                        currency,
+                        &ctx.accounts.seller_token_account,
+                        &ctx.accounts.seller,
+                    )?,
+                    platform_wallet: ctx.accounts.platform_wallet.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    referral: None,
+                },
+            },
+            &royalties,
+            ctx.accounts.marketplace.bump,
+            total_price,
+            amount,
+            platform_fee,
+            ctx.accounts.marketplace.secondary_fee,
+        )?;
+        ctx.accounts.seller_stats.record_sale(total_price);
+        
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection = collection_key(&ctx.accounts.nft_mint.key(), &ctx.accounts.collection_mint);
+        collection_stats.payment_mint = order.payment_mint;
+        collection_stats.bump = *ctx.bumps.get("collection_stats").unwrap();
+        collection_stats.record_sale(total_price, amount)?;
+        
+        let order_state = &mut ctx.accounts.order_state;
+        order_state.seller = order.seller;
+        order_state.nonce = order.nonce;
+        order_state.bump = *ctx.bumps.get("order_state").unwrap();
+        order_state.filled = filled;
+        
+        emit!(OrderFilled {
+            seller: order.seller,
+            nonce: order.nonce,
+            buyer: ctx.accounts.buyer.key(),
+            nft_mint: order.nft_mint,
+            price: order.price,
+            amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Cancels a signed order so it can no longer be filled
+    ///
+    /// Revoking the marketplace's delegate approval also invalidates all of a
+    /// seller's open orders for that token account at once.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * nonce - Nonce of the order to cancel
+    pub fn cancel_order(ctx: Context<CancelOrder>, nonce: u64) -> Result<()> {
+        let order_state = &mut ctx.accounts.order_state;
+        order_state.seller = ctx.accounts.seller.key();
+        order_state.nonce = nonce;
+        order_state.bump = *ctx.bumps.get("order_state").unwrap();
+        order_state.cancelled = true;
+        
+        emit!(OrderCancelled {
+            seller: order_state.seller,
+            nonce,
+        });
+        
+        Ok(())
+    }
+
+    /// Registers a referrer for sales paid in one currency
+    ///
+    /// SPL referrals get a vault that accrues their fee share; SOL referrals
+    /// accrue lamports on the referral account.
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    /// * payment_currency - Whether the referral earns native SOL or `payment_mint` tokens
+    pub fn register_referrer(
+        ctx: Context<RegisterReferrer>,
+        payment_currency: PaymentCurrency,
+    ) -> Result<()> {
+        let is_sol = payment_currency == PaymentCurrency::Sol;
+        require!(
+            !is_sol || ctx.accounts.payment_mint.key() == native_mint::ID,
+            ErrorCode::InvalidPaymentCurrency
+        );
+        require!(
+            ctx.accounts.referral_vault.is_some() != is_sol,
+            ErrorCode::InvalidPaymentAccount
+        );
+        
+        let referral = &mut ctx.accounts.referral;
+        referral.referrer = ctx.accounts.referrer.key();
+        referral.payment_mint = ctx.accounts.payment_mint.key();
+        referral.payment_currency = payment_currency;
+        referral.bump = *ctx.bumps.get("referral").unwrap();
+        referral.vault = ctx
+            .accounts
+            .referral_vault
+            .as_ref()
+            .map_or_else(Pubkey::default, |vault| vault.key());
+        referral.claimed = 0;
+        
+        emit!(ReferrerRegistered {
+            referral: referral.key(),
+            referrer: referral.referrer,
+            payment_mint: referral.payment_mint,
+        });
+        
+        Ok(())
+    }
+
+    /// Claims the referral fees accrued for a referrer
+    ///
+    /// # Arguments
+    /// * ctx - Context containing program accounts
+    pub fn claim_referral(ctx: Context<ClaimReferral>) -> Result<()> {
+        let referral = &ctx.accounts.referral;
+        let amount = match referral.payment_currency {
+            PaymentCurrency::Sol => {
+                let info = referral.to_account_info();
+                let reserve = Rent::get()?.minimum_balance(info.data_len());
+                let amount = info.lamports().saturating_sub(reserve);
+                move_lamports(&info, &ctx.accounts.referrer, amount)?;
+                amount
+            }
+            PaymentCurrency::Spl => {
+                let vault = ctx
+                    .accounts
+                    .referral_vault
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidReferralAccount)?;
+                let destination = ctx
+                    .accounts
+                    .referrer_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidPaymentAccount)?;
+                release_from_vault(
+                    &ctx.accounts.marketplace,
+                    vault,
+                    destination,
+                    &ctx.accounts.token_program,
+                    vault.amount,
+                )?;
+                vault.amount
+            }
+        };
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        let referral = &mut ctx.accounts.referral;
+        referral.claimed = referral.claimed.checked_add(amount).unwrap();
+        
+        emit!(ReferralClaimed {
+            referral: referral.key(),
+            referrer: referral.referrer,
+            amount,
+        });
+        
+        Ok(())
+    }
+}
+
+/// Accounts used to settle a purchase
+///
+/// The NFT is moved from `nft_source`, usually the listing escrow or a
+/// delegated seller account released by the marketplace PDA, and paid for
+/// through `payment`.
+pub struct PurchaseContext<'info> {
+    pub nft_source: AccountInfo<'info>,
+    pub nft_authority: AccountInfo<'info>,
+    pub buyer_token_account: AccountInfo<'info>,
+    pub payment: PaymentContext<'info>,
+}
+
+/// Accounts used to pay for a sale
+///
+/// Sale proceeds are drawn from `payment_source`, which is either the buyer's
+/// own token account or wallet, or a payment vault controlled by the
+/// marketplace PDA. Payment destinations are token accounts for SPL sales and
+/// wallets for SOL sales.
+pub struct PaymentContext<'info> {
+    pub currency: PaymentCurrency,
+    pub payment_source: AccountInfo<'info>,
+    pub payment_authority: AccountInfo<'info>,
+    pub seller_payment_account: AccountInfo<'info>,
+    pub platform_wallet: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub referral: Option<ReferralPayout<'info>>,
+}
+
+/// Creator royalty share paid out during a purchase
+pub struct RoyaltyPayout<'info> {
+    pub percentage: u16,
+    /// Part of the sale price the royalty is owed on, the whole price if unset
+    pub item_price: Option<u64>,
+    pub account: AccountInfo<'info>,
+}
+
+/// Referrer's share of the platform fee paid out during a purchase
+pub struct ReferralPayout<'info> {
+    /// Share of the platform fee in basis points
+    pub share: u16,
+    pub account: AccountInfo<'info>,
+}
+
+impl<'info> TryFrom<&BuyNft<'info>> for PurchaseContext<'info> {
+    type Error = anchor_lang::error::Error;
+
+    fn try_from(accounts: &BuyNft<'info>) -> Result<Self> {
+        let currency = accounts.listing.payment_currency;
+        Ok(Self {
+            nft_source: listed_tokens_account(
+                &accounts.listing,
+                &accounts.escrow_token_account,
+                &accounts.delegated_token_account,
+            )?,
+            nft_authority: accounts.marketplace.to_account_info(),
+            buyer_token_account: accounts.buyer_token_account.to_account_info(),
+            payment: PaymentContext {
+                currency,
+                payment_source: payment_account(
+                    currency,
+                    &accounts.buyer_payment_account,
+                    &accounts.buyer.to_account_info(),
+                )?,
+                payment_authority: accounts.buyer.to_account_info(),
+                seller_payment_account: payment_account(
+                    currency,
+                    &accounts.seller_token_account,
+                    &accounts.seller,
+                )?,
+                platform_wallet: accounts.platform_wallet.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+                referral: referral_payout(
+                    &accounts.marketplace,
+                    currency,
+                    accounts.listing.payment_mint,
+                    &accounts.referral,
+                    &accounts.referral_vault,
+                    accounts.buyer.key(),
+                )?,
+            },
+        })
+    }
+}
+
+impl<'info> TryFrom<&BuyNow<'info>> for PurchaseContext<'info> {
+    type Error = anchor_lang::error::Error;
+
+    fn try_from(accounts: &BuyNow<'info>) -> Result<Self> {
+        let currency = accounts.listing.payment_currency;
+        Ok(Self {
+            nft_source: accounts.escrow_token_account.to_account_info(),
+            nft_authority: accounts.marketplace.to_account_info(),
+            buyer_token_account: accounts.buyer_token_account.to_account_info(),
+            payment: PaymentContext {
+                currency,
+                payment_source: payment_account(
+                    currency,
+                    &accounts.buyer_payment_account,
+                    &accounts.buyer.to_account_info(),
+                )?,
+                payment_authority: accounts.buyer.to_account_info(),
+                seller_payment_account: payment_account(
+                    currency,
+                    &accounts.seller_token_account,
+                    &accounts.seller,
+                )?,
+                platform_wallet: accounts.platform_wallet.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+                referral: None,
+            },
+        })
+    }
+}
+
+impl<'info> TryFrom<&FinalizeAuction<'info>> for PurchaseContext<'info> {
+    type Error = anchor_lang::error::Error;
+
+    fn try_from(accounts: &FinalizeAuction<'info>) -> Result<Self> {
+        let currency = accounts.listing.payment_currency;
+        require!(
+            accounts.referral.is_some() == accounts.listing.bid_referral.is_some(),
+            ErrorCode::InvalidReferralAccount
+        );
+        Ok(Self {
+            nft_source: accounts.escrow_token_account.to_account_info(),
+            nft_authority: accounts.marketplace.to_account_info(),
+            buyer_token_account: accounts
+                .winner_token_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidBuyerAccount)?
+                .to_account_info(),
+            payment: PaymentContext {
+                currency,
+                payment_source: payment_vault_account(
+                    currency,
+                    &accounts.payment_vault,
+                    &accounts.sol_vault,
+                )?,
+                payment_authority: accounts.marketplace.to_account_info(),
+                seller_payment_account: payment_account(
+                    currency,
+                    &accounts.seller_token_account,
+                    &accounts.seller,
+                )?,
+                platform_wallet: accounts.platform_wallet.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+                referral: referral_payout(
+                    &accounts.marketplace,
+                    currency,
+                    accounts.listing.payment_mint,
+                    &accounts.referral,
+                    &accounts.referral_vault,
+                    accounts.listing.highest_bidder.unwrap_or_default(),
+                )?,
+            },
+        })
+    }
+}
+
+/// Internal function to process purchase and distribute funds
+///
+/// The NFT transfer is signed with the marketplace PDA seeds so that escrowed
+/// tokens can be released. When the seller delivers directly the seeds are
+/// unused.
+fn process_purchase<'info>(
+    ctx: PurchaseContext<'info>,
+    royalties: &[RoyaltyPayout<'info>],
+    marketplace_bump: u8,
+    sale_price: u64,
+    amount: u64,
+    platform_fee: u16,
+    secondary_fee: u16,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace_bump]];
+    let signer = &[seeds];
+    
+    // Transfer NFT to buyer
+    let cpi_accounts = Transfer {
+        from: ctx.nft_source.clone(),
+        to: ctx.buyer_token_account.clone(),
+        authority: ctx.nft_authority.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(ctx.payment.token_program.clone(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)?;
+    
+    distribute_payment(
+        &ctx.payment,
+        royalties,
+        marketplace_bump,
+        sale_price,
+        platform_fee,
+        secondary_fee,
+    )
+}
+
+/// Distributes a sale price between the platform, creators and seller
+///
+/// The platform receives the platform fee plus the secondary sale fee, less
+/// the referrer's share of the platform fee when a referrer is present, and
+/// each creator receives their royalty share; the seller gets the rest.
+/// Token transfers are signed with the marketplace PDA seeds so that vault
+/// funds can be released. When the buyer pays directly the seeds are unused.
+fn distribute_payment<'info>(
+    ctx: &PaymentContext<'info>,
+    royalties: &[RoyaltyPayout<'info>],
+    marketplace_bump: u8,
+    sale_price: u64,
+    platform_fee: u16,
+    secondary_fee: u16,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace_bump]];
+    let signer = &[seeds];
+    
+    // Calculate platform and secondary sale fees
+    let fee_amount = sale_price
+        .checked_mul(u64::from(platform_fee) + u64::from(secondary_fee))
+        .unwrap()
+        .checked_div(BASIS_POINTS.into())
+        .unwrap();
+    
+    let mut remaining_amount = sale_price.checked_sub(fee_amount).unwrap();
+    
+    // Referrer's share comes out of the platform fee only
+    let referral_amount = match &ctx.referral {
+        Some(referral) => sale_price
+            .checked_mul(platform_fee.into())
+            .unwrap()
+            .checked_div(BASIS_POINTS.into())
+            .unwrap()
+            .checked_mul(referral.share.into())
+            .unwrap()
+            .checked_div(BASIS_POINTS.into())
+            .unwrap(),
+        None => 0,
+    };
+    
+    // Pays from the payment source in the sale currency
+    let pay = |to: &AccountInfo<'info>, amount: u64| -> Result<()> {
+        match ctx.currency {
+            PaymentCurrency::Spl => {
+                let cpi_accounts = Transfer {
+                    from: ctx.payment_source.clone(),
+                    to: to.clone(),
+                    authority: ctx.payment_authority.clone(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(ctx.token_program.clone(), cpi_accounts, signer);
+                token::transfer(cpi_ctx, amount)
+            }
+            PaymentCurrency::Sol if ctx.payment_source.owner == &crate::ID => {
+                move_lamports(&ctx.payment_source, to, amount)
+            }
+            PaymentCurrency::Sol => {
+                let cpi_accounts = system_program::Transfer {
+                    from: ctx.payment_source.clone(),
+                    to: to.clone(),
+                };
+                let cpi_ctx = CpiContext::new(ctx.system_program.clone(), cpi_accounts);
+                system_program::transfer(cpi_ctx, amount)
+            }
+        }
+    };
+    
+    // Transfer platform fee and referral share
+    pay(&ctx.platform_wallet, fee_amount.checked_sub(referral_amount).unwrap())?;
+    if let Some(referral) = &ctx.referral {
+        pay(&referral.account, referral_amount)?;
+    }
+    
+    // Pay creator royalties
+    for royalty in royalties {
+        let royalty_amount = royalty
+            .item_price
+            .unwrap_or(sale_price)
+            .checked_mul(royalty.percentage.into())
+            .unwrap()
+            .checked_div(BASIS_POINTS.into())
+            .unwrap();
+        remaining_amount = remaining_amount
+            .checked_sub(royalty_amount)
+            .ok_or(ErrorCode::RoyaltyTooHigh)?;
+        pay(&royalty.account, royalty_amount)?;
+    }
+    
+    // Transfer remaining to seller
+    pay(&ctx.seller_payment_account, remaining_amount)?;
+    
+    Ok(())
+}
+
+/// Resolves the creator royalties owed on a sale of `nft_mint`
+///
+/// Tokens registered to a music_nft edition pay royalties to the edition's
+/// recipients. The edition must be passed whenever the mint has a collection
+/// record, and the remaining accounts must hold one payment account per
+/// royalty recipient, in the edition's order: a token account for SPL sales
+/// or the recipient's wallet for SOL sales.
+fn load_royalties<'info>(
+    marketplace: &Marketplace,
+    collection_mint: &AccountInfo<'info>,
+    edition: Option<&AccountInfo<'info>>,
+    recipient_accounts: &[AccountInfo<'info>],
+    currency: PaymentCurrency,
+    payment_mint: Pubkey,
+    max_royalty: u16,
+) -> Result<Vec<RoyaltyPayout<'info>>> {
+    let Some(collection) = registered_collection(collection_mint) else {
+        return Ok(Vec::new());
+    };
+    let edition = edition.ok_or(ErrorCode::InvalidEditionAccount)?;
+    require!(
+        edition.key() == collection && marketplace.is_music_nft_edition(edition),
+        ErrorCode::InvalidEditionAccount
+    );
+    
+    let data = edition.try_borrow_data()?;
+    let edition = EditionRoyalties::deserialize(&mut &data[8..])?;
+    require!(edition.total_royalty_percentage <= max_royalty, ErrorCode::RoyaltyTooHigh);
+    require!(
+        recipient_accounts.len() == edition.royalty_recipients.len(),
+        ErrorCode::InvalidRoyaltyAccount
+    );
+    
+    let mut royalties = Vec::with_capacity(recipient_accounts.len());
+    for (split, account_info) in edition.royalty_recipients.iter().zip(recipient_accounts) {
+        match currency {
+            PaymentCurrency::Spl => {
+                let token_account = Account::<TokenAccount>::try_from(account_info)?;
+                require!(
+                    token_account.owner == split.recipient && token_account.mint == payment_mint,
+                    ErrorCode::InvalidRoyaltyAccount
+                );
+            }
+            PaymentCurrency::Sol => {
+                require!(account_info.key() == split.recipient, ErrorCode::InvalidRoyaltyAccount);
+            }
+        }
+        royalties.push(RoyaltyPayout {
+            percentage: split.percentage,
+            item_price: None,
+            account: account_info.clone(),
+        });
+    }
+    
+    Ok(royalties)
+}
+
+/// Computes a bid or reserve commitment: keccak(amount_le || salt)
+pub fn commitment_hash(amount: u64, salt: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[&amount.to_le_bytes(), salt]).to_bytes()
+}
+
+/// Checks that the previous instruction verified the seller's signature over `order`
+///
+/// The ed25519 program instruction must hold exactly one signature whose
+/// public key and message are stored in its own instruction data.
+fn verify_order_signature(instructions: &AccountInfo, order: &SignedOrder) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, ErrorCode::InvalidOrderSignature);
+    let ix = load_instruction_at_checked((current_index - 1).into(), instructions)?;
+    require!(
+        ix.program_id == ed25519_program::ID && ix.accounts.is_empty(),
+        ErrorCode::InvalidOrderSignature
+    );
+    
+    // Header: signature count and padding, then a single set of offsets
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, ErrorCode::InvalidOrderSignature);
+    let read_u16 = |at: usize| usize::from(u16::from_le_bytes([data[at], data[at + 1]]));
+    let this_instruction = usize::from(u16::MAX);
+    require!(
+        read_u16(4) == this_instruction
+            && read_u16(8) == this_instruction
+            && read_u16(14) == this_instruction,
+        ErrorCode::InvalidOrderSignature
+    );
+    
+    let public_key_offset = read_u16(6);
+    let message_offset = read_u16(10);
+    let message_size = read_u16(12);
+    let public_key = data.get(public_key_offset..public_key_offset + 32);
+    let message = data.get(message_offset..message_offset + message_size);
+    require!(
+        public_key == Some(order.seller.as_ref()) && message == Some(order.message()?.as_slice()),
+        ErrorCode::InvalidOrderSignature
+    );
+    
+    Ok(())
+}
+
+/// Transfers tokens out of a marketplace-controlled escrow or payment vault
+fn release_from_vault<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    vault: &Account<'info, TokenAccount>,
+    destination: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace.bump]];
+    let signer = &[seeds];
+    let cpi_accounts = Transfer {
+        from: vault.to_account_info(),
+        to: destination.to_account_info(),
+        authority: marketplace.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+/// Closes an emptied listing escrow or payment vault, returning its rent to the seller
+fn close_escrow<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    escrow_token_account: &Account<'info, TokenAccount>,
+    seller: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace.bump]];
+    let signer = &[seeds];
+    let cpi_accounts = CloseAccount {
+        account: escrow_token_account.to_account_info(),
+        destination: seller.clone(),
+        authority: marketplace.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::close_account(cpi_ctx)
+}
+
+/// Picks the account a listing's tokens are sold from: its escrow, or the
+/// seller's delegated token account for escrow-less listings
+fn listed_tokens_account<'info>(
+    listing: &Listing,
+    escrow_token_account: &Option<Account<'info, TokenAccount>>,
+    delegated_token_account: &Option<Account<'info, TokenAccount>>,
+) -> Result<AccountInfo<'info>> {
+    let account = match listing.delegated_account {
+        None => escrow_token_account.as_ref(),
+        Some(_) => delegated_token_account.as_ref(),
+    };
+    account
+        .map(|account| account.to_account_info())
+        .ok_or_else(|| error!(ErrorCode::InvalidEscrowAccount))
+}
+
+/// Freezes a seller token account backing escrow-less listings
+///
+/// Only possible when the marketplace PDA is the mint's freeze authority;
+/// otherwise the tokens stay movable and the listing fails to fill if the
+/// seller moves them.
+fn freeze_delegated<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    token_account: &AccountInfo<'info>,
+    mint: &Account<'info, Mint>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    if mint.freeze_authority != COption::Some(marketplace.key()) {
+        return Ok(());
+    }
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace.bump]];
+    let signer = &[seeds];
+    let cpi_accounts = FreezeAccount {
+        account: token_account.clone(),
+        mint: mint.to_account_info(),
+        authority: marketplace.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::freeze_account(cpi_ctx)
+}
+
+/// Thaws a seller token account frozen by `freeze_delegated`
+fn thaw_delegated<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    token_account: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    if mint.freeze_authority != COption::Some(marketplace.key()) || !token_account.is_frozen() {
+        return Ok(());
+    }
+    let seeds: &[&[u8]] = &[b"marketplace", &[marketplace.bump]];
+    let signer = &[seeds];
+    let cpi_accounts = ThawAccount {
+        account: token_account.to_account_info(),
+        mint: mint.to_account_info(),
+        authority: marketplace.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::thaw_account(cpi_ctx)
+}
+
+/// Freezes a seller token account again after a sale from an escrow-less
+/// listing, while the marketplace remains delegate of tokens listed elsewhere
+fn refreeze_delegated<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    token_account: &AccountInfo<'info>,
+    mint: &Account<'info, Mint>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let account = Account::<TokenAccount>::try_from(token_account)?;
+    if account.delegate != COption::Some(marketplace.key()) || account.delegated_amount == 0 {
+        return Ok(());
+    }
+    freeze_delegated(marketplace, token_account, mint, token_program)
+}
+
+/// Reduces the marketplace's delegation over a seller token account by
+/// `amount`, revoking it once nothing is left listed
+///
+/// The account stays frozen while other escrow-less listings still use it.
+fn release_delegation<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    token_account: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    seller: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    thaw_delegated(marketplace, token_account, mint, token_program)?;
+    if token_account.delegate != COption::Some(marketplace.key()) {
+        return Ok(());
+    }
+    
+    let remaining = token_account.delegated_amount.saturating_sub(amount);
+    if remaining == 0 {
+        let cpi_accounts = Revoke {
+            source: token_account.to_account_info(),
+            authority: seller.clone(),
+        };
+        let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+        return token::revoke(cpi_ctx);
+    }
+    
+    let cpi_accounts = Approve {
+        to: token_account.to_account_info(),
+        delegate: marketplace.to_account_info(),
+        authority: seller.clone(),
+    };
+    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token::approve(cpi_ctx, remaining)?;
+    freeze_delegated(marketplace, &token_account.to_account_info(), mint, token_program)
+}
+
+/// Moves every bundle item out of escrow and closes the item escrows
+///
+/// `accounts` holds, for each item in order, its escrow and a token account
+/// of `recipient` for the item's mint. Escrow rent is returned to the seller.
+fn release_bundle_items<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    bundle: &Account<'info, Bundle>,
+    accounts: &[AccountInfo<'info>],
+    recipient: Pubkey,
+    seller: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    require!(
+        accounts.len() == bundle.items.len() * 2,
+        ErrorCode::InvalidBundleAccounts
+    );
+    
+    let bundle_key = bundle.key();
+    for (item, accounts) in bundle.items.iter().zip(accounts.chunks(2)) {
+        let escrow_key = Pubkey::create_program_address(
+            &[
+                b"bundle_escrow",
+                bundle_key.as_ref(),
+                item.mint.as_ref(),
+                &[item.escrow_bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidBundleAccounts))?;
+        require!(accounts[0].key() == escrow_key, ErrorCode::InvalidBundleAccounts);
+        let escrow = Account::<TokenAccount>::try_from(&accounts[0])?;
+        let destination = Account::<TokenAccount>::try_from(&accounts[1])?;
+        require!(
+            destination.mint == item.mint && destination.owner == recipient,
+            ErrorCode::InvalidBundleAccounts
+        );
+        
+        release_from_vault(marketplace, &escrow, &destination, token_program, item.amount)?;
+        close_escrow(marketplace, &escrow, seller, token_program)?;
+    }
+    
+    Ok(())
+}
+
+/// Loads a collection stats account passed in remaining accounts, creating it
+/// on the collection's first sale in `payment_mint`
+fn load_collection_stats<'info>(
+    collection_stats: &AccountInfo<'info>,
+    collection: Pubkey,
+    payment_mint: Pubkey,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<Account<'info, CollectionStats>> {
+    let (stats_key, bump) = Pubkey::find_program_address(
+        &[b"collection_stats", collection.as_ref(), payment_mint.as_ref()],
+        &crate::ID,
+    );
+    require!(collection_stats.key() == stats_key, ErrorCode::InvalidBundleAccounts);
+    if !collection_stats.data_is_empty() {
+        return Account::<CollectionStats>::try_from(collection_stats);
+    }
+    
+    let seeds: &[&[u8]] = &[b"collection_stats", collection.as_ref(), payment_mint.as_ref(), &[bump]];
+    let signer = &[seeds];
+    let space = 8 + CollectionStats::MAX_SIZE;
+    let cpi_accounts = CreateAccount {
+        from: payer.to_account_info(),
+        to: collection_stats.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+    system_program::create_account(cpi_ctx, Rent::get()?.minimum_balance(space), space as u64, &crate::ID)?;
+    collection_stats.try_borrow_mut_data()?[..8].copy_from_slice(&CollectionStats::DISCRIMINATOR);
+    
+    let mut stats = Account::<CollectionStats>::try_from(collection_stats)?;
+    stats.collection = collection;
+    stats.payment_mint = payment_mint;
+    stats.bump = bump;
+    Ok(stats)
+}
+
+/// Resolves where the referrer's fee share is paid, if a referrer was passed
+///
+/// SPL shares accrue in the referral vault and SOL shares as lamports on the
+/// referral account itself, until claimed with `claim_referral`. Buyers cannot
+/// refer themselves to rebate part of the platform fee.
+fn referral_payout<'info>(
+    marketplace: &Marketplace,
+    currency: PaymentCurrency,
+    payment_mint: Pubkey,
+    referral: &Option<Account<'info, Referral>>,
+    referral_vault: &Option<Account<'info, TokenAccount>>,
+    buyer: Pubkey,
+) -> Result<Option<ReferralPayout<'info>>> {
+    let Some(referral) = referral else {
+        return Ok(None);
+    };
+    require!(
+        referral.payment_mint == payment_mint
+            && referral.payment_currency == currency
+            && referral.referrer != buyer,
+        ErrorCode::InvalidReferralAccount
+    );
+    
+    let account = match currency {
+        PaymentCurrency::Sol => referral.to_account_info(),
+        PaymentCurrency::Spl => {
+            let vault = referral_vault.as_ref().ok_or(ErrorCode::InvalidReferralAccount)?;
+            require!(vault.key() == referral.vault, ErrorCode::InvalidReferralAccount);
+            vault.to_account_info()
+        }
+    };
+    
+    Ok(Some(ReferralPayout {
+        share: marketplace.referral_share,
+        account,
+    }))
+}
+
+/// Collection a token belongs to: the music_nft edition its mint is
+/// registered to, or the mint itself for unregistered tokens
+pub fn collection_key(mint: &Pubkey, collection_mint: &AccountInfo) -> Pubkey {
+    registered_collection(collection_mint).unwrap_or(*mint)
+}
+
+/// Picks the account that sends or receives a payment in the listing currency
+///
+/// SPL payments use the given token account; SOL payments use the wallet itself.
+fn payment_account<'info>(
+    currency: PaymentCurrency,
+    token_account: &Option<Account<'info, TokenAccount>>,
+    wallet: &AccountInfo<'info>,
+) -> Result<AccountInfo<'info>> {
+    match currency {
+        PaymentCurrency::Spl => token_account
+            .as_ref()
+            .map(|token_account| token_account.to_account_info())
+            .ok_or_else(|| error!(ErrorCode::InvalidPaymentAccount)),
+        PaymentCurrency::Sol => Ok(wallet.clone()),
+    }
+}
+
+/// Collection registered for a mint, if any
+///
+/// `collection_mint` must be the mint's `[b"collection_mint", mint]` address;
+/// it holds a `CollectionMint` record only when the mint was registered.
+pub fn registered_collection(collection_mint: &AccountInfo) -> Option<Pubkey> {
+    read_record::<CollectionMint>(collection_mint).map(|record| record.collection)
+}
+
+/// Reads a program record that may not exist at its fixed address
+///
+/// Records like collection mints and fee overrides matter only when present,
+/// so instructions require their address through seeds and read them here.
+fn read_record<T: AccountDeserialize>(info: &AccountInfo) -> Option<T> {
+    if info.owner != &crate::ID {
+        return None;
+    }
+    let data = info.try_borrow_data().ok()?;
+    T::try_deserialize(&mut &data[..]).ok()
+}
+
+/// Picks the listing payment vault matching the listing currency
+fn payment_vault_account<'info>(
+    currency: PaymentCurrency,
+    payment_vault: &Option<Account<'info, TokenAccount>>,
+    sol_vault: &Option<Account<'info, SolVault>>,
+) -> Result<AccountInfo<'info>> {
+    let vault = match currency {
+        PaymentCurrency::Spl => payment_vault.as_ref().map(|vault| vault.to_account_info()),
+        PaymentCurrency::Sol => sol_vault.as_ref().map(|vault| vault.to_account_info()),
+    };
+    vault.ok_or_else(|| error!(ErrorCode::InvalidPaymentVault))
+}
+
+/// Moves a payment from a signing payer into a listing payment vault
+///
+/// SPL payments come from the payer's token account; SOL payments are a
+/// system transfer from the payer's wallet.
+fn deposit_payment<'info>(
+    currency: PaymentCurrency,
+    payer: &Signer<'info>,
+    payer_token_account: &Option<Account<'info, TokenAccount>>,
+    vault: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+) -> Result<()> {
+    match currency {
+        PaymentCurrency::Spl => {
+            let cpi_accounts = Transfer {
+                from: payment_account(currency, payer_token_account, payer)?,
+                to: vault,
+                authority: payer.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+            token::transfer(cpi_ctx, amount)
+        }
+        PaymentCurrency::Sol => {
+            let cpi_accounts = system_program::Transfer {
+                from: payer.to_account_info(),
+                to: vault,
+            };
+            let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+            system_program::transfer(cpi_ctx, amount)
+        }
+    }
+}
+
+/// Pays out of a listing payment vault, e.g. to refund a bidder
+///
+/// Whichever vault is supplied determines the currency: SPL payments go to the
+/// recipient's token account, SOL payments straight to the recipient's wallet.
+fn release_payment<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    payment_vault: &Option<Account<'info, TokenAccount>>,
+    sol_vault: &Option<Account<'info, SolVault>>,
+    recipient: &AccountInfo<'info>,
+    recipient_token_account: &Option<Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    match (payment_vault, sol_vault) {
+        (Some(payment_vault), _) => {
+            let recipient_token_account = recipient_token_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidPaymentAccount)?;
+            release_from_vault(marketplace, payment_vault, recipient_token_account, token_program, amount)
+        }
+        (None, Some(sol_vault)) => move_lamports(&sol_vault.to_account_info(), recipient, amount),
+        (None, None) => err!(ErrorCode::InvalidPaymentVault),
+    }
+}
+
+/// Moves lamports out of a program-owned account such as a SOL vault
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    **from.try_borrow_mut_lamports()? = from.lamports().checked_sub(amount).unwrap();
+    **to.try_borrow_mut_lamports()? = to.lamports().checked_add(amount).unwrap();
+    Ok(())
+}
+
+/// Closes the listing payment vault once it holds no funds, returning its rent to the seller
+fn close_payment_vault_if_empty<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    payment_vault: &mut Option<Account<'info, TokenAccount>>,
+    sol_vault: &Option<Account<'info, SolVault>>,
+    seller: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    if let Some(payment_vault) = payment_vault {
+        payment_vault.reload()?;
+        if payment_vault.amount == 0 {
+            close_escrow(marketplace, payment_vault, seller, token_program)?;
+        }
+    }
+    if let Some(sol_vault) = sol_vault {
+        if SolVault::balance(sol_vault)? == 0 {
+            sol_vault.close(seller.clone())?;
+        }
+    }
+    Ok(())
+}
+
+/// Accounts for initialization
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Marketplace::MAX_SIZE,
+        seeds = [b"marketplace"],
+        bump
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for authority-only marketplace updates
+#[derive(Accounts)]
+pub struct UpdateMarketplace<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    pub authority: Signer<'info>,
+}
+
+/// Accounts for accepting the marketplace authority
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = marketplace.pending_authority == Some(new_authority.key()) @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    pub new_authority: Signer<'info>,
+}
+
+/// Accounts for setting a collection fee override
+#[derive(Accounts)]
+#[instruction(collection: Pubkey)]
+pub struct SetCollectionFee<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + CollectionFee::MAX_SIZE,
+        seeds = [b"collection_fee", collection.as_ref()],
+        bump
+    )]
+    pub collection_fee: Account<'info, CollectionFee>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for removing a collection fee override
+#[derive(Accounts)]
+pub struct RemoveCollectionFee<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"collection_fee", collection_fee.collection.as_ref()],
+        bump = collection_fee.bump,
+        close = authority
+    )]
+    pub collection_fee: Account<'info, CollectionFee>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+/// Accounts for setting the volume fee tiers of a payment mint
+#[derive(Accounts)]
+#[instruction(payment_mint: Pubkey)]
+pub struct SetFeeTiers<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + FeeSchedule::MAX_SIZE,
+        seeds = [b"fee_schedule", payment_mint.as_ref()],
+        bump
+    )]
+    pub fee_schedule: Account<'info, FeeSchedule>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for adding an allowlist entry
+#[derive(Accounts)]
+#[instruction(asset: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AllowlistEntry::MAX_SIZE,
+        seeds = [b"allowlist", asset.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for removing an allowlist entry
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"allowlist", allowlist_entry.asset.as_ref()],
+        bump = allowlist_entry.bump,
+        close = authority
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+/// Accounts for registering a collection mint
+#[derive(Accounts)]
+pub struct RegisterCollectionMint<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + CollectionMint::MAX_SIZE,
+        seeds = [b"collection_mint", mint.key().as_ref()],
+        bump
+    )]
+    pub collection_mint: Account<'info, CollectionMint>,
+    pub mint: Account<'info, Mint>,
+    /// CHECK: music_nft edition the mint belongs to, validated by owner and discriminator
+    #[account(constraint = marketplace.is_music_nft_edition(&edition) @ ErrorCode::InvalidEditionAccount)]
+    pub edition: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for removing a collection mint record
+#[derive(Accounts)]
+pub struct UnregisterCollectionMint<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"collection_mint", collection_mint.mint.as_ref()],
+        bump = collection_mint.bump,
+        close = authority
+    )]
+    pub collection_mint: Account<'info, CollectionMint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
 
 /// Accounts for listing an NFT
 #[derive(Accounts)]
+#[instruction(nft_mint: Pubkey, nonce: u64)]
 pub struct ListNft<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
     pub marketplace: Account<'info, Marketplace>,
-    #[account(init, payer = seller, space = 8 + Listing::MAX_SIZE)]
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Listing::MAX_SIZE,
+        seeds = [b"listing", seller.key().as_ref(), nft_mint.as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
     pub listing: Account<'info, Listing>,
     #[account(mut)]
     pub seller: Signer<'info>,
+    #[account(address = nft_mint)]
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"allowlist", allowlist_entry.asset.as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    #[account(mut, token::mint = nft_mint, token::authority = seller)]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::MAX_SIZE,
+        seeds = [b"seller_stats", seller.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: Fee schedule address of the payment mint, read if it exists
+    #[account(seeds = [b"fee_schedule", payment_mint.key().as_ref()], bump)]
+    pub fee_schedule: UncheckedAccount<'info>,
+    /// CHECK: Fee override address of the NFT's collection, read if it exists
+    #[account(
+        seeds = [b"collection_fee", collection_key(&nft_mint, &collection_mint).as_ref()],
+        bump
+    )]
+    pub collection_fee: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + SolVault::MAX_SIZE,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::MAX_SIZE,
+        seeds = [b"collection_stats", collection_key(&nft_mint, &collection_mint).as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for cancelling a listing
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump,
+        has_one = seller @ ErrorCode::NotSeller,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
     #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = seller)]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(
+        mut,
+        constraint = highest_bid_refund.listing == listing.key()
+            && Some(highest_bid_refund.bidder) == listing.highest_bidder
+            @ ErrorCode::InvalidRefundAccount
+    )]
+    pub highest_bid_refund: Option<Account<'info, BidRefund>>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for updating a listing
+#[derive(Accounts)]
+pub struct UpdateListing<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump,
+        has_one = seller @ ErrorCode::NotSeller
+    )]
+    pub listing: Account<'info, Listing>,
+    pub seller: Signer<'info>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = seller)]
     pub seller_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for cleaning up an expired listing
+#[derive(Accounts)]
+pub struct CrankExpiredListing<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump,
+        close = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Receives escrow and listing rent, validated against the listing
+    /// seller; signs to release the delegation of escrow-less listings
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = listing.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for refreshing a collection's floor
+#[derive(Accounts)]
+pub struct RefreshFloor<'info> {
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+}
+
+/// Accounts for renting an NFT
+#[derive(Accounts)]
+pub struct RentNft<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub renter: Signer<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = renter)]
+    pub renter_payment_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Receives SOL rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for reclaiming a rented NFT
+#[derive(Accounts)]
+pub struct ReclaimRental<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Receives escrow and listing rent, validated against the listing
+    /// seller; closes the listing when signing
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = listing.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for buying an NFT
+#[derive(Accounts)]
+pub struct BuyNft<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    #[account(mut, token::mint = listing.nft_mint)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = listing.delegated_account == Some(delegated_token_account.key()) @ ErrorCode::InvalidEscrowAccount
+    )]
+    pub delegated_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Receives escrow and listing rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub referral: Option<Account<'info, Referral>>,
+    #[account(mut)]
+    pub referral_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", listing.seller.as_ref(), listing.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for buying several listings at once
+#[derive(Accounts)]
+pub struct BuyListings<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(mut)]
+    pub platform_wallet: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for placing a bid
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidRefund::MAX_SIZE,
+        seeds = [b"bid_refund", listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub bid_refund: Account<'info, BidRefund>,
+    #[account(
+        mut,
+        constraint = prev_bid_refund.listing == listing.key()
+            && Some(prev_bid_refund.bidder) == listing.highest_bidder
+            @ ErrorCode::InvalidRefundAccount
+    )]
+    pub prev_bid_refund: Option<Account<'info, BidRefund>>,
+    pub referral: Option<Account<'info, Referral>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for finalizing an auction
+#[derive(Accounts)]
+pub struct FinalizeAuction<'info> {
+    #[account(mut, seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        token::mint = listing.nft_mint,
+        constraint = Some(winner_token_account.owner) == listing.highest_bidder @ ErrorCode::InvalidBuyerAccount
+    )]
+    pub winner_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Receives the proceeds and escrow and listing rent, validated
+    /// against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = listing.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = Some(referral.key()) == listing.bid_referral @ ErrorCode::InvalidReferralAccount
+    )]
+    pub referral: Option<Account<'info, Referral>>,
+    #[account(mut)]
+    pub referral_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = highest_bid_refund.listing == listing.key()
+            && Some(highest_bid_refund.bidder) == listing.highest_bidder
+            @ ErrorCode::InvalidRefundAccount
+    )]
+    pub highest_bid_refund: Option<Account<'info, BidRefund>>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", listing.seller.as_ref(), listing.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for buying an auction at its buy-now price
+#[derive(Accounts)]
+pub struct BuyNow<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    #[account(mut, token::mint = listing.nft_mint)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Receives escrow and listing rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = highest_bid_refund.listing == listing.key()
+            && Some(highest_bid_refund.bidder) == listing.highest_bidder
+            @ ErrorCode::InvalidRefundAccount
+    )]
+    pub highest_bid_refund: Option<Account<'info, BidRefund>>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", listing.seller.as_ref(), listing.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for revealing a hidden reserve price
+#[derive(Accounts)]
+pub struct RevealReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump,
+        has_one = seller @ ErrorCode::NotSeller
+    )]
+    pub listing: Account<'info, Listing>,
+    pub seller: Signer<'info>,
+}
+
+/// Accounts for committing a sealed bid
+#[derive(Accounts)]
+pub struct CommitBid<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + SealedBid::MAX_SIZE,
+        seeds = [b"sealed_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for revealing a sealed bid
+#[derive(Accounts)]
+pub struct RevealBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"sealed_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump = sealed_bid.bump,
+        has_one = bidder
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+}
+
+/// Accounts for settling a sealed-bid auction
+#[derive(Accounts)]
+pub struct SettleSealedAuction<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        token::mint = listing.nft_mint,
+        constraint = Some(winner_token_account.owner) == listing.highest_bidder @ ErrorCode::InvalidBuyerAccount
+    )]
+    pub winner_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sealed_bid", listing.key().as_ref(), winner_bid.bidder.as_ref()],
+        bump = winner_bid.bump,
+        constraint = Some(winner_bid.bidder) == listing.highest_bidder @ ErrorCode::InvalidBuyerAccount
+    )]
+    pub winner_bid: Option<Account<'info, SealedBid>>,
+    /// CHECK: Receives escrow and listing rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = listing.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", listing.seller.as_ref(), listing.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for withdrawing a sealed-bid deposit
+#[derive(Accounts)]
+pub struct WithdrawSealedDeposit<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"sealed_bid", sealed_bid.listing.as_ref(), bidder.key().as_ref()],
+        bump = sealed_bid.bump,
+        has_one = bidder,
+        close = bidder
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", sealed_bid.listing.as_ref()],
+        bump,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", sealed_bid.listing.as_ref()],
+        bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Listing the bid belongs to, which may already be closed
+    #[account(address = sealed_bid.listing)]
+    pub listing: AccountInfo<'info>,
+    /// CHECK: Receives payment vault rent, validated against the bid record
+    #[account(mut, address = sealed_bid.seller)]
+    pub seller: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for withdrawing a bid refund
+#[derive(Accounts)]
+pub struct WithdrawRefund<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"bid_refund", bid_refund.listing.as_ref(), bidder.key().as_ref()],
+        bump = bid_refund.bump,
+        has_one = bidder
+    )]
+    pub bid_refund: Account<'info, BidRefund>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", bid_refund.listing.as_ref()],
+        bump,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", bid_refund.listing.as_ref()],
+        bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Listing the refund belongs to, which may already be closed
+    #[account(address = bid_refund.listing)]
+    pub listing: AccountInfo<'info>,
+    /// CHECK: Receives payment vault rent, validated against the refund record
+    #[account(mut, address = bid_refund.seller)]
+    pub seller: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for placing a uniform-price auction bid
+#[derive(Accounts)]
+pub struct PlaceUnitBid<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + UnitBid::MAX_SIZE,
+        seeds = [b"unit_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub unit_bid: Account<'info, UnitBid>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidRefund::MAX_SIZE,
+        seeds = [b"bid_refund", listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub bid_refund: Account<'info, BidRefund>,
+    /// Lowest bid, leaving a full auction
+    #[account(mut, constraint = outbid.listing == listing.key() @ ErrorCode::InvalidBidAccounts)]
+    pub outbid: Option<Account<'info, UnitBid>>,
+    #[account(
+        mut,
+        constraint = outbid_refund.listing == listing.key() @ ErrorCode::InvalidRefundAccount
+    )]
+    pub outbid_refund: Option<Account<'info, BidRefund>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for settling a uniform-price auction
+#[derive(Accounts)]
+pub struct SettleUnitAuction<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    /// CHECK: Receives SOL proceeds and rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = listing.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = listing.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    /// CHECK: Platform fee destination: a token account for SPL listings (the mint
+    /// is enforced by the token program) or a wallet for SOL listings
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(listing.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", listing.seller.as_ref(), listing.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"collection_stats", listing.collection.as_ref(), listing.payment_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for claiming a uniform-price auction bid
+#[derive(Accounts)]
+pub struct ClaimUnitBid<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref(), &listing.nonce.to_le_bytes()],
+        bump = listing.bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        seeds = [b"unit_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump = unit_bid.bump,
+        has_one = bidder,
+        close = bidder
+    )]
+    pub unit_bid: Account<'info, UnitBid>,
     #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(mut, token::mint = listing.nft_mint, token::authority = bidder)]
+    pub bidder_nft_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = listing.payment_mint, token::authority = bidder)]
+    pub bidder_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow", listing.key().as_ref()],
+        bump = listing.escrow_bump,
+        token::mint = listing.nft_mint,
+        token::authority = marketplace
+    )]
     pub escrow_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"payment_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump,
+        token::mint = listing.payment_mint,
+        token::authority = marketplace
+    )]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"sol_vault", listing.key().as_ref()],
+        bump = listing.payment_vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Receives escrow, vault and listing rent, validated against the listing seller
+    #[account(mut, address = listing.seller)]
+    pub seller: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for listing a bundle
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct ListBundle<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Bundle::MAX_SIZE,
+        seeds = [b"bundle", seller.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::MAX_SIZE,
+        seeds = [b"seller_stats", seller.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: Fee schedule address of the payment mint, read if it exists
+    #[account(seeds = [b"fee_schedule", payment_mint.key().as_ref()], bump)]
+    pub fee_schedule: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for buying a bundle
+#[derive(Accounts)]
+pub struct BuyBundle<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"bundle", bundle.seller.as_ref(), &bundle.nonce.to_le_bytes()],
+        bump = bundle.bump,
+        close = seller
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(mut, token::mint = bundle.payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Receives escrow and bundle rent, validated against the bundle seller
+    #[account(mut, address = bundle.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = bundle.payment_mint, token::authority = bundle.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL bundles (the mint
+    /// is enforced by the token program) or a wallet for SOL bundles
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(bundle.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"seller_stats", bundle.seller.as_ref(), bundle.payment_mint.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for cancelling a bundle
+#[derive(Accounts)]
+pub struct CancelBundle<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"bundle", bundle.seller.as_ref(), &bundle.nonce.to_le_bytes()],
+        bump = bundle.bump,
+        has_one = seller @ ErrorCode::NotSeller,
+        close = seller
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for making an offer
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct MakeOffer<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + Offer::MAX_SIZE,
+        seeds = [b"offer", buyer.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + SolVault::MAX_SIZE,
+        seeds = [b"offer_sol_vault", offer.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for cancelling an offer
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"offer", offer.buyer.as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(mut, token::mint = offer.payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump,
+        token::mint = offer.payment_mint,
+        token::authority = marketplace
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"offer_sol_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Accounts for accepting an offer
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"offer", offer.buyer.as_ref(), &offer.nonce.to_le_bytes()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump,
+        token::mint = offer.payment_mint,
+        token::authority = marketplace
+    )]
+    pub offer_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"offer_sol_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub sol_vault: Option<Account<'info, SolVault>>,
+    /// CHECK: Receives offer rent once filled, validated against the offer buyer
+    #[account(mut, address = offer.buyer)]
+    pub buyer: AccountInfo<'info>,
+    #[account(mut, token::mint = nft_mint, token::authority = offer.buyer)]
+    pub buyer_nft_account: Account<'info, TokenAccount>,
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(mut, token::mint = nft_mint, token::authority = seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = offer.payment_mint, token::authority = seller)]
+    pub seller_payment_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL offers (the mint
+    /// is enforced by the token program) or a wallet for SOL offers
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(offer.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::MAX_SIZE,
+        seeds = [b"collection_stats", collection_key(&nft_mint.key(), &collection_mint).as_ref(), offer.payment_mint.as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::MAX_SIZE,
+        seeds = [b"seller_stats", seller.key().as_ref(), offer.payment_mint.as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: Fee schedule address of the payment mint, read if it exists
+    #[account(seeds = [b"fee_schedule", offer.payment_mint.as_ref()], bump)]
+    pub fee_schedule: UncheckedAccount<'info>,
+    /// CHECK: Fee override address of the NFT's collection, read if it exists
+    #[account(
+        seeds = [b"collection_fee", collection_key(&nft_mint.key(), &collection_mint).as_ref()],
+        bump
+    )]
+    pub collection_fee: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
-/// Accounts for buying an NFT
+/// Accounts for filling a signed order
 #[derive(Accounts)]
-pub struct BuyNft<'info> {
-    #[account(mut)]
+#[instruction(order: SignedOrder)]
+pub struct FillOrder<'info> {
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        constraint = !marketplace.paused @ ErrorCode::MarketplacePaused
+    )]
     pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub listing: Account<'info, Listing>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + OrderState::MAX_SIZE,
+        seeds = [b"order", order.seller.as_ref(), &order.nonce.to_le_bytes()],
+        bump
+    )]
+    pub order_state: Account<'info, OrderState>,
     #[account(mut)]
     pub buyer: Signer<'info>,
-    #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub platform_wallet: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = nft_mint, token::authority = buyer)]
+    pub buyer_nft_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = order.payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: Option<Account<'info, TokenAccount>>,
+    #[account(address = order.nft_mint)]
+    pub nft_mint: Account<'info, Mint>,
+    /// CHECK: Collection record of the NFT mint, read with `registered_collection`
+    #[account(seeds = [b"collection_mint", nft_mint.key().as_ref()], bump)]
+    pub collection_mint: UncheckedAccount<'info>,
+    /// CHECK: music_nft edition, validated against the registered collection
+    pub edition: Option<UncheckedAccount<'info>>,
+    /// CHECK: Receives SOL proceeds, validated against the order seller
+    #[account(mut, address = order.seller)]
+    pub seller: AccountInfo<'info>,
+    #[account(mut, token::mint = nft_mint, token::authority = order.seller)]
+    pub seller_nft_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = order.payment_mint, token::authority = order.seller)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Platform fee destination: a token account for SPL orders (the mint
+    /// is enforced by the token program) or a wallet for SOL orders
+    #[account(
+        mut,
+        constraint = marketplace.is_treasury_wallet(order.payment_currency, &platform_wallet)
+            @ ErrorCode::InvalidTreasury
+    )]
+    pub platform_wallet: UncheckedAccount<'info>,
+    /// CHECK: Instructions sysvar, used to find the ed25519 signature check
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::MAX_SIZE,
+        seeds = [b"collection_stats", collection_key(&nft_mint.key(), &collection_mint).as_ref(), order.payment_mint.as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SellerStats::MAX_SIZE,
+        seeds = [b"seller_stats", order.seller.as_ref(), order.payment_mint.as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: Fee schedule address of the payment mint, read if it exists
+    #[account(seeds = [b"fee_schedule", order.payment_mint.as_ref()], bump)]
+    pub fee_schedule: UncheckedAccount<'info>,
+    /// CHECK: Fee override address of the NFT's collection, read if it exists
+    #[account(
+        seeds = [b"collection_fee", collection_key(&nft_mint.key(), &collection_mint).as_ref()],
+        bump
+    )]
+    pub collection_fee: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-/// Accounts for placing a bid
+/// Accounts for cancelling a signed order
 #[derive(Accounts)]
-pub struct PlaceBid<'info> {
+#[instruction(nonce: u64)]
+pub struct CancelOrder<'info> {
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + OrderState::MAX_SIZE,
+        seeds = [b"order", seller.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub order_state: Account<'info, OrderState>,
     #[account(mut)]
+    pub seller: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for registering a referrer
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
     pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + Referral::MAX_SIZE,
+        seeds = [b"referral", referrer.key().as_ref(), payment_mint.key().as_ref()],
+        bump
+    )]
+    pub referral: Account<'info, Referral>,
+    #[account(
+        init,
+        payer = referrer,
+        seeds = [b"referral_vault", referral.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = marketplace
+    )]
+    pub referral_vault: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
-    pub listing: Account<'info, Listing>,
-    #[account(mut)]
-    pub bidder: Signer<'info>,
-    #[account(mut)]
-    pub bidder_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
+    pub referrer: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for claiming referral fees
+#[derive(Accounts)]
+pub struct ClaimReferral<'info> {
+    #[account(seeds = [b"marketplace"], bump = marketplace.bump)]
+    pub marketplace: Account<'info, Marketplace>,
+    #[account(
+        mut,
+        seeds = [b"referral", referrer.key().as_ref(), referral.payment_mint.as_ref()],
+        bump = referral.bump,
+        has_one = referrer
+    )]
+    pub referral: Account<'info, Referral>,
+    #[account(mut, address = referral.vault)]
+    pub referral_vault: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
-    pub prev_bidder_token_account: Account<'info, TokenAccount>,
+    pub referrer: Signer<'info>,
+    #[account(mut, token::mint = referral.payment_mint, token::authority = referrer)]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
     pub token_program: Program<'info, Token>,
 }
 
-/// Accounts for finalizing an auction
-#[derive(Accounts)]
-pub struct FinalizeAuction<'info> {
-    #[account(mut)]
-    pub marketplace: Account<'info, Marketplace>,
-    #[account(mut)]
-    pub listing: Account<'info, Listing>,
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub platform_wallet: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+/// Marketplace state account
+#[account]
+pub struct Marketplace {
+    pub bump: u8,
+    pub platform_fee: u16,
+    pub secondary_fee: u16,
+    pub listing_count: u64,
+    pub allow_cancel_with_bids: bool,
+    pub referral_share: u16,
+    pub authority: Pubkey,
+    pub pending_authority: Option<Pubkey>,
+    pub treasury: Pubkey,
+    pub paused: bool,
+    pub music_nft_program: Pubkey,
+}
+
+impl Marketplace {
+    pub const MAX_SIZE: usize = 1 + 2 + 2 + 8 + 1 + 2 + 32 + (1 + 32) + 32 + 1 + 32; // bump + fees + listing_count + allow_cancel_with_bids + referral_share + authority + pending_authority + treasury + paused + music_nft_program
+
+    /// Platform fee for a new listing
+    ///
+    /// A collection fee override takes precedence; otherwise the fee of the
+    /// highest volume tier the seller has reached applies, falling back to the
+    /// base platform fee.
+    pub fn listing_fee(
+        &self,
+        collection_fee: Option<&CollectionFee>,
+        fee_schedule: Option<&FeeSchedule>,
+        seller_volume: u64,
+    ) -> u16 {
+        if let Some(collection_fee) = collection_fee {
+            return collection_fee.platform_fee;
+        }
+        fee_schedule
+            .and_then(|schedule| {
+                schedule
+                    .tiers
+                    .iter()
+                    .rev()
+                    .find(|tier| seller_volume >= tier.min_volume)
+            })
+            .map_or(self.platform_fee, |tier| tier.platform_fee)
+    }
+
+    /// Whether `platform_wallet` pays fees into the treasury in `currency`
+    ///
+    /// SOL fees go to the treasury wallet itself and SPL fees to a token
+    /// account owned by it.
+    pub fn is_treasury_wallet(&self, currency: PaymentCurrency, platform_wallet: &AccountInfo) -> bool {
+        match currency {
+            PaymentCurrency::Sol => *platform_wallet.key == self.treasury,
+            PaymentCurrency::Spl => {
+                platform_wallet.owner == &token::ID
+                    && platform_wallet
+                        .try_borrow_data()
+                        .ok()
+                        .and_then(|data| TokenAccount::try_deserialize(&mut &data[..]).ok())
+                        .is_some_and(|account| account.owner == self.treasury)
+            }
+        }
+    }
+
+    /// Whether `edition` is an `Edition` account of the music_nft program
+    pub fn is_music_nft_edition(&self, edition: &AccountInfo) -> bool {
+        *edition.owner == self.music_nft_program
+            && edition
+                .try_borrow_data()
+                .is_ok_and(|data| data.len() >= 8 && data[..8] == EditionRoyalties::discriminator())
+    }
+}
+
+/// Asset accepted for listing
+///
+/// `asset` is either a single mint, a music_nft edition covering the mints
+/// registered to it, or the music_nft program covering every registered mint.
+#[account]
+pub struct AllowlistEntry {
+    pub asset: Pubkey,
+    pub bump: u8,
+}
+
+impl AllowlistEntry {
+    pub const MAX_SIZE: usize = 32 + 1; // asset + bump
+
+    /// Whether tokens of `mint`, registered to `collection`, may be listed
+    /// under this entry
+    ///
+    /// Registration checks the edition belongs to the music_nft program, so
+    /// every registered mint is covered by the program's entry.
+    pub fn allows(&self, marketplace: &Marketplace, mint: &Pubkey, collection: Option<Pubkey>) -> bool {
+        *mint == self.asset
+            || collection == Some(self.asset)
+            || (collection.is_some() && self.asset == marketplace.music_nft_program)
+    }
+}
+
+/// Membership of a mint in a music_nft edition collection
+///
+/// Registered by the marketplace authority. The record lives at the mint's
+/// `[b"collection_mint", mint]` address, so instructions take that address
+/// unconditionally and read it with `registered_collection`.
+#[account]
+pub struct CollectionMint {
+    pub mint: Pubkey,
+    pub collection: Pubkey,
+    pub bump: u8,
+}
+
+impl CollectionMint {
+    pub const MAX_SIZE: usize = 32 + 32 + 1; // mint + collection + bump
+}
+
+/// Platform fee override for a partner collection
+#[account]
+pub struct CollectionFee {
+    pub collection: Pubkey,
+    pub platform_fee: u16,
+    pub bump: u8,
+}
+
+impl CollectionFee {
+    pub const MAX_SIZE: usize = 32 + 2 + 1; // collection + platform_fee + bump
+}
+
+/// Volume-based platform fee tiers of a payment mint
+///
+/// Sellers only earn tiers in currencies the authority set a schedule for, so
+/// volume in worthless tokens never lowers their fees.
+#[account]
+pub struct FeeSchedule {
+    pub payment_mint: Pubkey,
+    pub bump: u8,
+    pub tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+    pub const MAX_SIZE: usize = 32 + 1 + 4 + MAX_FEE_TIERS * FeeTier::MAX_SIZE; // payment_mint + bump + tiers
+}
+
+/// Platform fee for sellers above a cumulative sale volume
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub platform_fee: u16,
+}
+
+impl FeeTier {
+    pub const MAX_SIZE: usize = 8 + 2; // min_volume + platform_fee
+}
+
+/// Trade statistics of a collection in one payment mint
+///
+/// Updated on every listing, offer, order and bundle sale. The floor book keeps
+/// the `MAX_FLOOR_LISTINGS` cheapest active fixed-price listings, so the floor
+/// moves on to the next listing when the cheapest one sells out, is cancelled
+/// or is repriced. Listings outside the book only enter it through
+/// `refresh_floor`.
+#[account]
+pub struct CollectionStats {
+    pub collection: Pubkey,
+    pub payment_mint: Pubkey,
+    pub bump: u8,
+    pub last_price: u64,
+    pub last_sale_at: i64,
+    pub volume: u64,
+    pub sales: u64,
+    pub floor_price: u64,
+    pub floor_listing: Option<Pubkey>,
+    /// Whether active fixed-price listings may exist outside the floor book
+    pub untracked: bool,
+    pub floor_book: Vec<FloorEntry>,
+}
+
+impl CollectionStats {
+    pub const MAX_SIZE: usize = 32 + 32 + 1 + 8 + 8 + 8 + 8 // collection + payment_mint + bump + last sale + volume + sales
+        + 8 + (1 + 32) + 1 // floor + untracked
+        + (4 + MAX_FLOOR_LISTINGS * FloorEntry::MAX_SIZE); // floor_book
+
+    /// Records a sale of `amount` tokens for `total_price`
+    pub fn record_sale(&mut self, total_price: u64, amount: u64) -> Result<()> {
+        self.last_price = total_price.checked_div(amount).unwrap_or_default();
+        self.last_sale_at = Clock::get()?.unix_timestamp;
+        self.volume = self.volume.checked_add(total_price).unwrap();
+        self.sales = self.sales.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// Inserts an active fixed-price listing into the floor book if it is
+    /// among the cheapest, noting when a listing is left out or evicted
+    pub fn offer_floor(&mut self, listing: Pubkey, unit_price: u64) {
+        self.floor_book.retain(|entry| entry.listing != listing);
+        let position = self
+            .floor_book
+            .partition_point(|entry| entry.unit_price <= unit_price);
+        if position < MAX_FLOOR_LISTINGS {
+            self.floor_book.insert(position, FloorEntry { listing, unit_price });
+        }
+        if position >= MAX_FLOOR_LISTINGS || self.floor_book.len() > MAX_FLOOR_LISTINGS {
+            self.floor_book.truncate(MAX_FLOOR_LISTINGS);
+            self.untracked = true;
+        }
+        self.sync_floor();
+    }
+
+    /// Follows a price change of a listing in the floor book
+    ///
+    /// Listings outside the book are ignored. A listing raised past the last
+    /// entry leaves the book if listings outside it may now be cheaper.
+    pub fn reprice_floor(&mut self, listing: Pubkey, unit_price: u64) {
+        let Some(index) = self.floor_book.iter().position(|entry| entry.listing == listing) else {
+            return;
+        };
+        self.floor_book.remove(index);
+        let position = self
+            .floor_book
+            .partition_point(|entry| entry.unit_price <= unit_price);
+        if position < self.floor_book.len() || !self.untracked {
+            self.offer_floor(listing, unit_price);
+        } else {
+            self.sync_floor();
+        }
+    }
+
+    /// Removes a listing that sold out, was cancelled or expired from the
+    /// floor book
+    pub fn remove_listing(&mut self, listing: Pubkey) {
+        self.floor_book.retain(|entry| entry.listing != listing);
+        self.sync_floor();
+    }
+
+    /// Mirrors the cheapest book entry into the floor fields
+    fn sync_floor(&mut self) {
+        let floor = self.floor_book.first();
+        self.floor_listing = floor.map(|entry| entry.listing);
+        self.floor_price = floor.map_or(0, |entry| entry.unit_price);
+    }
+}
+
+/// Active fixed-price listing in a collection's floor book
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FloorEntry {
+    pub listing: Pubkey,
+    pub unit_price: u64,
+}
+
+impl FloorEntry {
+    pub const MAX_SIZE: usize = 32 + 8; // listing + unit_price
+}
+
+/// Cumulative sales of a seller in one payment mint, used for its fee tiers
+#[account]
+pub struct SellerStats {
+    pub seller: Pubkey,
+    pub payment_mint: Pubkey,
+    pub bump: u8,
+    pub volume: u64,
+    pub sales: u64,
+}
+
+impl SellerStats {
+    pub const MAX_SIZE: usize = 32 + 32 + 1 + 8 + 8; // seller + payment_mint + bump + volume + sales
+
+    /// Adds a completed sale to the seller's totals
+    pub fn record_sale(&mut self, price: u64) {
+        self.volume = self.volume.checked_add(price).unwrap();
+        self.sales = self.sales.checked_add(1).unwrap();
+    }
+}
+
+/// Listing account
+#[account]
+pub struct Listing {
+    pub id: u64,
+    pub nonce: u64,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    pub payment_vault_bump: u8,
+    pub nft_mint: Pubkey,
+    /// Collection the listing's trade statistics are kept under, fixed at
+    /// listing time
+    pub collection: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_currency: PaymentCurrency,
+    pub price: u64,
+    pub seller: Pubkey,
+    pub kind: ListingKind,
+    pub auction_end: i64,
+    pub highest_bid: u64,
+    pub second_highest_bid: u64,
+    pub highest_bidder: Option<Pubkey>,
+    /// Referral account attached to the highest bid
+    pub bid_referral: Option<Pubkey>,
+    pub bid_count: u32,
+    pub amount: u64,
+    pub platform_fee: u16,
+    pub min_bid_increment: BidIncrement,
+    pub soft_close_window: i64,
+    pub reserve_price: Option<ReservePrice>,
+    pub buy_now_price: Option<u64>,
+    pub expires_at: Option<i64>,
+    pub renter: Option<Pubkey>,
+    pub rented_until: i64,
+    pub clearing_price: Option<u64>,
+    /// Seller token account the marketplace PDA is delegate of, for
+    /// escrow-less listings
+    pub delegated_account: Option<Pubkey>,
+}
+
+impl Listing {
+    pub const MAX_SIZE: usize = 8 + 8 + 1 + 1 + 1 // id + nonce + bumps
+        + 32 + 32 + 32 + 1 + 8 + 32 // nft_mint + collection + payment_mint + payment_currency + price + seller
+        + ListingKind::MAX_SIZE + 8 // kind + auction_end
+        + 8 + 8 + (1 + 32) + (1 + 32) + 4 // highest_bid + second_highest_bid + highest_bidder + bid_referral + bid_count
+        + 8 + 2 // amount + platform_fee
+        + BidIncrement::MAX_SIZE + 8 // min_bid_increment + soft_close_window
+        + (1 + ReservePrice::MAX_SIZE) + (1 + 8) // reserve_price + buy_now_price
+        + (1 + 8) // expires_at
+        + (1 + 32) + 8 // renter + rented_until
+        + (1 + 8) // clearing_price
+        + (1 + 32); // delegated_account
+
+    /// Whether a rental of the listing is still running at `now`
+    pub fn is_rented(&self, now: i64) -> bool {
+        self.renter.is_some() && now < self.rented_until
+    }
+
+    /// Whether the listing has passed its expiry
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// Current price per token for a direct purchase at `now`
+    pub fn purchase_price(&self, now: i64) -> Result<u64> {
+        require!(self.price > 0, ErrorCode::NotForSale);
+        require!(!self.is_expired(now), ErrorCode::ListingExpired);
+        match self.kind {
+            ListingKind::FixedPrice => Ok(self.price),
+            ListingKind::EnglishAuction
+            | ListingKind::SealedBidAuction { .. }
+            | ListingKind::UniformPriceAuction => err!(ErrorCode::UseBidFunction),
+            ListingKind::DutchAuction { .. } => self.kind.dutch_price(now),
+            ListingKind::Rental { .. } => err!(ErrorCode::InvalidListingKind),
+        }
+    }
+}
+
+/// Currency a listing is paid in
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentCurrency {
+    /// Native SOL, held in a lamport `SolVault` while bids are pending
+    Sol,
+    /// SPL tokens of the listing's payment mint
+    Spl,
+}
+
+/// Lamport vault holding SOL bids and deposits for a listing or offer
+#[account]
+pub struct SolVault {}
+
+impl SolVault {
+    pub const MAX_SIZE: usize = 0; // lamports only
+
+    /// Lamports held above the rent-exempt reserve
+    pub fn balance(vault: &Account<SolVault>) -> Result<u64> {
+        let info = vault.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(info.data_len());
+        Ok(info.lamports().saturating_sub(reserve))
+    }
+}
+
+/// Sale terms of a new listing
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ListingParams {
+    /// Listing type (Dutch and sealed-bid auctions carry their own schedule)
+    pub kind: ListingKind,
+    /// Duration of English or uniform-price auction in seconds (if applicable)
+    pub auction_duration: u64,
+    /// Minimum raise over the highest bid (auctions only)
+    pub min_bid_increment: BidIncrement,
+    /// Seconds before the end in which a bid extends the auction (0 to disable)
+    pub soft_close_window: u64,
+    /// Optional public or hidden reserve price (auctions only)
+    pub reserve_price: Option<ReservePrice>,
+    /// Optional price that ends the auction immediately (auctions only)
+    pub buy_now_price: Option<u64>,
+    /// Whether buyers pay in native SOL or in `payment_mint` tokens
+    pub payment_currency: PaymentCurrency,
+    /// Optional timestamp after which the listing can no longer be bought
+    /// (fixed-price and Dutch listings only)
+    pub expires_at: Option<i64>,
+}
+
+/// Type of sale a listing runs
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ListingKind {
+    /// Sold at the listing price through `buy_nft`
+    FixedPrice,
+    /// Ascending bids through `place_bid`, settled by `finalize_auction`
+    EnglishAuction,
+    /// Descending price bought through `buy_nft`
+    ///
+    /// The price decays linearly from `start_price` at `start_time` to
+    /// `end_price` at `end_time`, in steps of `step_interval` seconds
+    /// (0 for continuous decay), and stays at `end_price` afterwards.
+    DutchAuction {
+        start_price: u64,
+        end_price: u64,
+        start_time: i64,
+        end_time: i64,
+        step_interval: i64,
+    },
+    /// Sealed bids committed through `commit_bid` until `bidding_end`,
+    /// revealed through `reveal_bid` until `reveal_end`, then settled at the
+    /// second-highest price by `settle_sealed_auction`
+    SealedBidAuction {
+        bidding_end: i64,
+        reveal_end: i64,
+    },
+    /// Rented out through `rent_nft` at the listing price per day, for up to
+    /// `max_days` days at a time
+    ///
+    /// The NFT stays in escrow; the current renter and rental end are
+    /// recorded on the listing for token-gated apps to check.
+    Rental {
+        max_days: u16,
+    },
+    /// Multi-unit bids of a quantity and unit price through `place_unit_bid`,
+    /// settled by `settle_unit_auction`
+    ///
+    /// Units go to the highest bids first, and every winner pays the unit
+    /// price of the lowest winning bid.
+    UniformPriceAuction,
+}
+
+impl ListingKind {
+    pub const MAX_SIZE: usize = 1 + 8 + 8 + 8 + 8 + 8; // variant + largest payload
+
+    /// Whether the listing collects bids in a payment vault
+    pub fn takes_bids(&self) -> bool {
+        matches!(
+            self,
+            ListingKind::EnglishAuction
+                | ListingKind::SealedBidAuction { .. }
+                | ListingKind::UniformPriceAuction
+        )
+    }
+
+    /// Returns the end of the bidding and reveal phases of a sealed-bid auction
+    pub fn sealed_bid_phases(&self) -> Result<(i64, i64)> {
+        match *self {
+            ListingKind::SealedBidAuction { bidding_end, reveal_end } => Ok((bidding_end, reveal_end)),
+            _ => err!(ErrorCode::InvalidListingKind),
+        }
+    }
+
+    /// Returns the Dutch auction price per token at `now`
+    pub fn dutch_price(&self, now: i64) -> Result<u64> {
+        let (start_price, end_price, start_time, end_time, step_interval) = match *self {
+            ListingKind::DutchAuction { start_price, end_price, start_time, end_time, step_interval } => {
+                (start_price, end_price, start_time, end_time, step_interval)
+            }
+            _ => return err!(ErrorCode::InvalidListingKind),
+        };
+        require!(now >= start_time, ErrorCode::AuctionNotStarted);
+        if now >= end_time {
+            return Ok(end_price);
+        }
+        
+        let mut elapsed = now - start_time;
+        if step_interval > 0 {
+            elapsed -= elapsed % step_interval;
+        }
+        let decay = (start_price - end_price) as u128 * elapsed as u128 / (end_time - start_time) as u128;
+        Ok(start_price - decay as u64)
+    }
+}
+
+/// Minimum raise required over the current highest bid
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BidIncrement {
+    /// Fixed amount of payment tokens
+    Absolute(u64),
+    /// Share of the current highest bid (basis points)
+    BasisPoints(u16),
+}
+
+impl BidIncrement {
+    pub const MAX_SIZE: usize = 1 + 8; // variant + largest payload
+
+    /// Returns the lowest acceptable bid over `highest_bid` (always at least one unit higher)
+    pub fn min_next_bid(&self, highest_bid: u64) -> u64 {
+        let increment = match *self {
+            BidIncrement::Absolute(amount) => amount,
+            // Basis points are capped at 100% when listing, so the increment fits in a u64
+            BidIncrement::BasisPoints(bps) => {
+                (highest_bid as u128 * bps as u128 / BASIS_POINTS as u128) as u64
+            }
+        };
+        highest_bid.checked_add(increment.max(1)).unwrap()
+    }
+}
+
+/// Reserve price of an auction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReservePrice {
+    /// Reserve visible to bidders
+    Public(u64),
+    /// Commitment to the reserve, see `commitment_hash`
+    Hidden([u8; 32]),
+}
+
+impl ReservePrice {
+    pub const MAX_SIZE: usize = 1 + 32; // variant + largest payload
+}
+
+/// Refundable balance of a bidder on an auction listing
+#[account]
+pub struct BidRefund {
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub seller: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl BidRefund {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 1; // listing + bidder + seller + amount + bump
+}
+
+/// Sealed bid committed to a sealed-bid auction listing
+#[account]
+pub struct SealedBid {
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub seller: Pubkey,
+    pub commitment: [u8; 32],
+    pub deposit: u64,
+    pub revealed: bool,
+    pub bump: u8,
+}
+
+impl SealedBid {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 32 + 8 + 1 + 1; // listing + bidder + seller + commitment + deposit + revealed + bump
+}
+
+/// Quantity bid in a uniform-price auction listing
+#[account]
+pub struct UnitBid {
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub quantity: u64,
+    pub unit_price: u64,
+    pub deposit: u64,
+    /// Units allocated at settlement
+    pub filled: u64,
+    pub placed_at: i64,
+    pub bump: u8,
+}
+
+impl UnitBid {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1; // listing + bidder + quantity + unit_price + deposit + filled + placed_at + bump
+}
+
+/// Several tokens listed together at a single price
+#[account]
+pub struct Bundle {
+    pub id: u64,
+    pub nonce: u64,
+    pub bump: u8,
+    pub seller: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_currency: PaymentCurrency,
+    pub price: u64,
+    pub platform_fee: u16,
+    pub items: Vec<BundleItem>,
+}
+
+impl Bundle {
+    pub const MAX_SIZE: usize = 8 + 8 + 1 + 32 // id + nonce + bump + seller
+        + 32 + 1 + 8 + 2 // payment_mint + payment_currency + price + platform_fee
+        + (4 + MAX_BUNDLE_ITEMS * BundleItem::MAX_SIZE); // items
+}
+
+/// Token held in a bundle escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BundleItem {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub escrow_bump: u8,
+}
+
+impl BundleItem {
+    pub const MAX_SIZE: usize = 32 + 8 + 1; // mint + amount + escrow bump
+}
+
+/// Listing to buy as part of `buy_listings`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchPurchase {
+    pub listing_id: u64,
+    pub amount: u64,
+    /// Number of remaining accounts used by this purchase
+    pub account_count: u8,
+}
+
+/// Standing offer escrowing a buyer's funds
+#[account]
+pub struct Offer {
+    pub buyer: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+    pub vault_bump: u8,
+    pub target: OfferTarget,
+    pub payment_mint: Pubkey,
+    pub payment_currency: PaymentCurrency,
+    pub price: u64,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub max_royalty: u16,
+}
+
+impl Offer {
+    pub const MAX_SIZE: usize = 32 + 8 + 1 + 1 + OfferTarget::MAX_SIZE + 32 + 1 + 8 + 8 + 8 + 2; // buyer + nonce + bumps + target + payment mint + currency + price + amount + expiry + max royalty
+}
+
+/// Tokens an offer can be accepted for
+///
+/// A collection is a music_nft edition; its tokens are the mints registered
+/// to it with a `CollectionMint` record.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OfferTarget {
+    /// A single token mint
+    Mint(Pubkey),
+    /// Any token registered to the given edition
+    Collection(Pubkey),
+}
+
+impl OfferTarget {
+    pub const MAX_SIZE: usize = 1 + 32; // variant + pubkey
+
+    /// Whether a token of `mint`, registered to `collection`, can fill the offer
+    pub fn matches(&self, mint: &Pubkey, collection: Option<Pubkey>) -> bool {
+        match *self {
+            OfferTarget::Mint(target) => *mint == target,
+            OfferTarget::Collection(target) => collection == Some(target),
+        }
+    }
+}
+
+/// Sell order signed off-chain by the seller and settled with `fill_order`
+///
+/// The seller approves the marketplace PDA as delegate of their token account
+/// instead of escrowing the NFT, so no listing account is created.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SignedOrder {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_currency: PaymentCurrency,
+    /// Price per token
+    pub price: u64,
+    pub amount: u64,
+    pub nonce: u64,
+    pub expires_at: i64,
+}
+
+impl SignedOrder {
+    /// Message the seller signs: a domain prefix, the program ID and the
+    /// serialized order
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message = b"music-marketplace-order".to_vec();
+        message.extend_from_slice(crate::ID.as_ref());
+        message.extend_from_slice(&self.try_to_vec()?);
+        Ok(message)
+    }
+}
+
+/// Fill and cancel state of a signed order
+#[account]
+pub struct OrderState {
+    pub seller: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+    pub filled: u64,
+    pub cancelled: bool,
+}
+
+impl OrderState {
+    pub const MAX_SIZE: usize = 32 + 8 + 1 + 8 + 1; // seller + nonce + bump + filled + cancelled
 }
 
-/// Marketplace state account
+/// Referral fee accrual for one referrer and payment currency
+///
+/// SPL fees accrue in the token account at `vault`; SOL fees accrue as
+/// lamports on this account.
 #[account]
-pub struct Marketplace {
+pub struct Referral {
+    pub referrer: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_currency: PaymentCurrency,
     pub bump: u8,
-    pub platform_fee: u16,
-    pub secondary_fee: u16,
+    pub vault: Pubkey,
+    pub claimed: u64,
 }
 
-impl Marketplace {
-    pub const MAX_SIZE: usize = 1 + 2 + 2; // bump + platform_fee + secondary_fee
+impl Referral {
+    pub const MAX_SIZE: usize = 32 + 32 + 1 + 1 + 32 + 8; // referrer + payment mint + currency + bump + vault + claimed
 }
 
-/// Listing account
-#[account]
-pub struct Listing {
-    pub nft_mint: Pubkey,
-    pub price: u64,
-    pub seller: Pubkey,
-    pub is_auction: bool,
-    pub auction_end: i64,
-    pub highest_bid: u64,
-    pub highest_bidder: Option<Pubkey>,
-    pub amount: u64,
-    pub platform_fee: u16,
+/// Royalty fields of a music_nft `Edition` account
+///
+/// Mirrors the leading fields of the music_nft account layout so the
+/// marketplace can read royalty splits without depending on that program.
+#[derive(AnchorDeserialize)]
+pub struct EditionRoyalties {
+    pub id: u64,
+    pub ipfs_hash: String,
+    pub max_supply: u64,
+    pub minted_count: u64,
+    pub total_royalty_percentage: u16,
+    pub is_active: bool,
+    pub royalty_recipients: Vec<RoyaltySplit>,
 }
 
-impl Listing {
-    pub const MAX_SIZE: usize = 32 + 8 + 32 + 1 + 8 + 8 + (1 + 32) + 8 + 2;
+impl EditionRoyalties {
+    /// Anchor account discriminator of the music_nft `Edition` account
+    pub fn discriminator() -> [u8; 8] {
+        let hash = anchor_lang::solana_program::hash::hash(b"account:Edition");
+        hash.to_bytes()[..8].try_into().unwrap()
+    }
+}
+
+/// Royalty recipient of a music_nft edition
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RoyaltySplit {
+    pub recipient: Pubkey,
+    pub percentage: u16,
 }
 
 /// Events
 #[event]
 pub struct NftListed {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub amount: u64,
+    pub kind: ListingKind,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub listing_id: u64,
     pub nft_mint: Pubkey,
     pub seller: Pubkey,
+}
+
+#[event]
+pub struct ListingUpdated {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
     pub price: u64,
     pub amount: u64,
-    pub is_auction: bool,
+    pub auction_end: i64,
+    pub expires_at: Option<i64>,
+}
+
+#[event]
+pub struct NftRented {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub renter: Pubkey,
+    pub price: u64,
+    pub rented_until: i64,
+}
+
+#[event]
+pub struct RentalReclaimed {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub renter: Pubkey,
+}
+
+#[event]
+pub struct ListingExpired {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub seller: Pubkey,
+    pub cranker: Pubkey,
+    pub reward: u64,
 }
 
 #[event]
 pub struct NftSold {
+    pub listing_id: u64,
     pub nft_mint: Pubkey,
     pub buyer: Pubkey,
     pub price: u64,
@@ -414,11 +6333,220 @@ pub struct NftSold {
 
 #[event]
 pub struct BidPlaced {
+    pub listing_id: u64,
     pub nft_mint: Pubkey,
     pub bidder: Pubkey,
     pub amount: u64,
 }
 
+#[event]
+pub struct AuctionExtended {
+    pub listing_id: u64,
+    pub auction_end: i64,
+}
+
+#[event]
+pub struct AuctionUnsold {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub highest_bid: u64,
+}
+
+#[event]
+pub struct ReserveRevealed {
+    pub listing_id: u64,
+    pub reserve_price: u64,
+}
+
+#[event]
+pub struct SealedBidCommitted {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub deposit: u64,
+}
+
+#[event]
+pub struct SealedBidRevealed {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BidOutbid {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RefundWithdrawn {
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UnitBidPlaced {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub quantity: u64,
+    pub unit_price: u64,
+}
+
+#[event]
+pub struct UnitAuctionSettled {
+    pub listing_id: u64,
+    pub nft_mint: Pubkey,
+    pub clearing_price: u64,
+    pub units_sold: u64,
+}
+
+#[event]
+pub struct UnitBidClaimed {
+    pub listing_id: u64,
+    pub bidder: Pubkey,
+    pub units: u64,
+    pub price: u64,
+    pub refund: u64,
+}
+
+#[event]
+pub struct BundleListed {
+    pub bundle_id: u64,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub mints: Vec<Pubkey>,
+}
+
+#[event]
+pub struct BundleSold {
+    pub bundle_id: u64,
+    pub buyer: Pubkey,
+    pub price: u64,
+}
+
+#[event]
+pub struct BundleCancelled {
+    pub bundle_id: u64,
+    pub seller: Pubkey,
+}
+
+#[event]
+pub struct OfferMade {
+    pub offer: Pubkey,
+    pub buyer: Pubkey,
+    pub target: OfferTarget,
+    pub price: u64,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct OfferCancelled {
+    pub offer: Pubkey,
+    pub buyer: Pubkey,
+}
+
+#[event]
+pub struct OfferAccepted {
+    pub offer: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OrderFilled {
+    pub seller: Pubkey,
+    pub nonce: u64,
+    pub buyer: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub seller: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub referral: Pubkey,
+    pub referrer: Pubkey,
+    pub payment_mint: Pubkey,
+}
+
+#[event]
+pub struct ReferralClaimed {
+    pub referral: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FeesUpdated {
+    pub platform_fee: u16,
+    pub secondary_fee: u16,
+    pub referral_share: u16,
+}
+
+#[event]
+pub struct TreasuryUpdated {
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct TradingPaused {
+    pub paused: bool,
+}
+
+#[event]
+pub struct AuthorityTransferStarted {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct CollectionFeeSet {
+    pub collection: Pubkey,
+    pub platform_fee: u16,
+}
+
+#[event]
+pub struct CollectionFeeRemoved {
+    pub collection: Pubkey,
+}
+
+#[event]
+pub struct FeeTiersUpdated {
+    pub payment_mint: Pubkey,
+    pub tiers: Vec<FeeTier>,
+}
+
+#[event]
+pub struct AllowlistUpdated {
+    pub asset: Pubkey,
+    pub allowed: bool,
+}
+
+#[event]
+pub struct CollectionMintUpdated {
+    pub mint: Pubkey,
+    pub collection: Pubkey,
+    pub registered: bool,
+}
+
 /// Error codes
 #[error_code]
 pub enum ErrorCode {
@@ -440,4 +6568,374 @@ pub enum ErrorCode {
     AuctionNotEnded,
     #[msg("No bids placed")]
     NoBids,
-}
\ No newline at end of file
+    #[msg("Listing ID does not match the listing account")]
+    ListingIdMismatch,
+    #[msg("Refund account does not belong to the previous bidder")]
+    InvalidRefundAccount,
+    #[msg("Only the seller can modify this listing")]
+    NotSeller,
+    #[msg("Auction already has bids")]
+    AuctionHasBids,
+    #[msg("Auction end must be in the future")]
+    InvalidAuctionEnd,
+    #[msg("Auction duration or soft-close window is out of range")]
+    InvalidAuctionDuration,
+    #[msg("A payment vault is required for auctions only")]
+    InvalidPaymentVault,
+    #[msg("Bid increment must be <= 100% (10000 basis points)")]
+    InvalidBidIncrement,
+    #[msg("Buy-now price must cover the starting and reserve prices")]
+    InvalidBuyNowPrice,
+    #[msg("Buy-now is not available for this listing")]
+    BuyNowUnavailable,
+    #[msg("Listing has no hidden reserve")]
+    NoHiddenReserve,
+    #[msg("Reserve price does not match the commitment")]
+    InvalidReserveReveal,
+    #[msg("Hidden reserve has not been revealed yet")]
+    ReserveNotRevealed,
+    #[msg("Operation not supported for this listing kind")]
+    InvalidListingKind,
+    #[msg("Dutch auction must decrease to a positive end price over a positive duration")]
+    InvalidDutchAuction,
+    #[msg("Auction has not started yet")]
+    AuctionNotStarted,
+    #[msg("Not in the reveal phase")]
+    NotRevealPhase,
+    #[msg("Bid has already been revealed")]
+    BidAlreadyRevealed,
+    #[msg("Bid does not match the commitment")]
+    InvalidBidReveal,
+    #[msg("Deposit does not cover the bid")]
+    InsufficientDeposit,
+    #[msg("Buyer account does not match the winning bidder")]
+    InvalidBuyerAccount,
+    #[msg("Deposit is locked until the auction is settled")]
+    DepositLocked,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Offer has expired")]
+    OfferExpired,
+    #[msg("NFT does not match the offer target")]
+    OfferTargetMismatch,
+    #[msg("Edition account is not the NFT's registered music_nft edition")]
+    InvalidEditionAccount,
+    #[msg("Royalty exceeds the accepted maximum")]
+    RoyaltyTooHigh,
+    #[msg("Royalty recipient accounts do not match the edition")]
+    InvalidRoyaltyAccount,
+    #[msg("SOL listings must use the native mint")]
+    InvalidPaymentCurrency,
+    #[msg("Payment account is required for SPL payments")]
+    InvalidPaymentAccount,
+    #[msg("Listing has expired")]
+    ListingExpired,
+    #[msg("Listing has not expired")]
+    ListingNotExpired,
+    #[msg("Bundle must hold between one and MAX_BUNDLE_ITEMS items")]
+    InvalidBundleSize,
+    #[msg("Remaining accounts do not match the bundle items")]
+    InvalidBundleAccounts,
+    #[msg("Remaining accounts do not match the batch purchases")]
+    InvalidBatchAccounts,
+    #[msg("Total price exceeds the accepted maximum")]
+    PriceExceedsMax,
+    #[msg("Rental price and duration must be positive and within the maximum")]
+    InvalidRentalTerms,
+    #[msg("Listing is currently rented")]
+    RentalActive,
+    #[msg("Listing has not been rented")]
+    NotRented,
+    #[msg("Missing or invalid ed25519 signature for the order")]
+    InvalidOrderSignature,
+    #[msg("Order has expired")]
+    OrderExpired,
+    #[msg("Order has been cancelled")]
+    OrderCancelled,
+    #[msg("Referral account does not match the sale currency")]
+    InvalidReferralAccount,
+    #[msg("Only the marketplace authority can do this")]
+    NotAuthority,
+    #[msg("Trading is paused")]
+    MarketplacePaused,
+    #[msg("Platform wallet does not belong to the treasury")]
+    InvalidTreasury,
+    #[msg("Fee tiers must be ordered by volume and within the tier limit")]
+    InvalidFeeTiers,
+    #[msg("NFT is not covered by the allowlist entry")]
+    AssetNotAllowed,
+    #[msg("Every bid of the auction must be passed once, ordered by address")]
+    InvalidBidAccounts,
+    #[msg("Auction has already been settled")]
+    AuctionSettled,
+    #[msg("Auction has not been settled yet")]
+    AuctionNotSettled,
+    #[msg("Not supported for escrow-less listings")]
+    DelegatedListing,
+    #[msg("Listed tokens account does not match the listing custody")]
+    InvalidEscrowAccount,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dutch(step_interval: i64) -> ListingKind {
+        ListingKind::DutchAuction {
+            start_price: 1_000,
+            end_price: 100,
+            start_time: 100,
+            end_time: 1_000,
+            step_interval,
+        }
+    }
+
+    fn marketplace() -> Marketplace {
+        Marketplace {
+            bump: 0,
+            platform_fee: 250,
+            secondary_fee: 0,
+            listing_count: 0,
+            allow_cancel_with_bids: false,
+            referral_share: 0,
+            authority: Pubkey::default(),
+            pending_authority: None,
+            treasury: Pubkey::default(),
+            paused: false,
+            music_nft_program: Pubkey::default(),
+        }
+    }
+
+    fn fee_schedule() -> FeeSchedule {
+        FeeSchedule {
+            payment_mint: Pubkey::default(),
+            bump: 0,
+            tiers: vec![
+                FeeTier { min_volume: 1_000, platform_fee: 200 },
+                FeeTier { min_volume: 10_000, platform_fee: 100 },
+            ],
+        }
+    }
+
+    fn collection_stats() -> CollectionStats {
+        CollectionStats {
+            collection: Pubkey::default(),
+            payment_mint: Pubkey::default(),
+            bump: 0,
+            last_price: 0,
+            last_sale_at: 0,
+            volume: 0,
+            sales: 0,
+            floor_price: 0,
+            floor_listing: None,
+            untracked: false,
+            floor_book: Vec::new(),
+        }
+    }
+
+    fn book(stats: &CollectionStats) -> Vec<u64> {
+        stats.floor_book.iter().map(|entry| entry.unit_price).collect()
+    }
+
+    /// Fills the floor book with listings priced 1 to `MAX_FLOOR_LISTINGS`
+    fn full_book() -> (CollectionStats, Vec<Pubkey>) {
+        let mut stats = collection_stats();
+        let listings: Vec<Pubkey> = (0..MAX_FLOOR_LISTINGS).map(|_| Pubkey::new_unique()).collect();
+        for (price, listing) in (1..).zip(&listings) {
+            stats.offer_floor(*listing, price);
+        }
+        (stats, listings)
+    }
+
+    #[test]
+    fn dutch_price_follows_schedule() {
+        let kind = dutch(0);
+        assert!(kind.dutch_price(99).is_err());
+        assert_eq!(kind.dutch_price(100).unwrap(), 1_000);
+        assert_eq!(kind.dutch_price(550).unwrap(), 550);
+        assert_eq!(kind.dutch_price(999).unwrap(), 101);
+        assert_eq!(kind.dutch_price(1_000).unwrap(), 100);
+        assert_eq!(kind.dutch_price(i64::MAX).unwrap(), 100);
+        assert!(ListingKind::FixedPrice.dutch_price(100).is_err());
+    }
+
+    #[test]
+    fn dutch_price_drops_in_steps() {
+        let kind = dutch(300);
+        assert_eq!(kind.dutch_price(399).unwrap(), 1_000);
+        assert_eq!(kind.dutch_price(400).unwrap(), 700);
+        assert_eq!(kind.dutch_price(999).unwrap(), 400);
+    }
+
+    #[test]
+    fn dutch_price_handles_full_price_range() {
+        let kind = ListingKind::DutchAuction {
+            start_price: u64::MAX,
+            end_price: 1,
+            start_time: 0,
+            end_time: i64::MAX,
+            step_interval: 0,
+        };
+        assert_eq!(kind.dutch_price(0).unwrap(), u64::MAX);
+        assert_eq!(kind.dutch_price(i64::MAX - 1).unwrap(), 3);
+    }
+
+    #[test]
+    fn min_next_bid_applies_increment() {
+        assert_eq!(BidIncrement::Absolute(50).min_next_bid(1_000), 1_050);
+        assert_eq!(BidIncrement::BasisPoints(500).min_next_bid(1_000), 1_050);
+        assert_eq!(BidIncrement::BasisPoints(BASIS_POINTS).min_next_bid(1_000), 2_000);
+    }
+
+    #[test]
+    fn min_next_bid_raises_by_at_least_one() {
+        assert_eq!(BidIncrement::Absolute(0).min_next_bid(1_000), 1_001);
+        assert_eq!(BidIncrement::BasisPoints(1).min_next_bid(10), 11);
+        assert_eq!(BidIncrement::BasisPoints(0).min_next_bid(0), 1);
+    }
+
+    #[test]
+    fn min_next_bid_handles_large_bids() {
+        let highest_bid = u64::MAX / 2;
+        assert_eq!(BidIncrement::BasisPoints(BASIS_POINTS).min_next_bid(highest_bid), u64::MAX - 1);
+        assert_eq!(BidIncrement::Absolute(1).min_next_bid(u64::MAX - 1), u64::MAX);
+    }
+
+    #[test]
+    #[should_panic]
+    fn min_next_bid_overflow_panics() {
+        BidIncrement::Absolute(1).min_next_bid(u64::MAX);
+    }
+
+    #[test]
+    fn listing_fee_uses_tier_reached() {
+        let marketplace = marketplace();
+        let schedule = fee_schedule();
+        assert_eq!(marketplace.listing_fee(None, None, u64::MAX), 250);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), 0), 250);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), 999), 250);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), 1_000), 200);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), 9_999), 200);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), 10_000), 100);
+        assert_eq!(marketplace.listing_fee(None, Some(&schedule), u64::MAX), 100);
+    }
+
+    #[test]
+    fn listing_fee_prefers_collection_override() {
+        let marketplace = marketplace();
+        let schedule = fee_schedule();
+        let collection_fee = CollectionFee {
+            collection: Pubkey::default(),
+            platform_fee: 500,
+            bump: 0,
+        };
+        assert_eq!(marketplace.listing_fee(Some(&collection_fee), None, 0), 500);
+        assert_eq!(marketplace.listing_fee(Some(&collection_fee), Some(&schedule), u64::MAX), 500);
+    }
+
+    #[test]
+    fn floor_book_keeps_cheapest_first() {
+        let mut stats = collection_stats();
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        stats.offer_floor(a, 300);
+        stats.offer_floor(b, 100);
+        stats.offer_floor(c, 200);
+        assert_eq!(book(&stats), vec![100, 200, 300]);
+        assert_eq!(stats.floor_listing, Some(b));
+        assert_eq!(stats.floor_price, 100);
+        
+        // Offering a listing again replaces its entry
+        stats.offer_floor(a, 50);
+        assert_eq!(book(&stats), vec![50, 100, 200]);
+        assert_eq!(stats.floor_listing, Some(a));
+    }
+
+    #[test]
+    fn floor_rolls_to_next_listing() {
+        let mut stats = collection_stats();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        stats.offer_floor(a, 100);
+        stats.offer_floor(b, 200);
+        
+        stats.remove_listing(a);
+        assert_eq!(stats.floor_listing, Some(b));
+        assert_eq!(stats.floor_price, 200);
+        
+        stats.remove_listing(b);
+        assert_eq!(stats.floor_listing, None);
+        assert_eq!(stats.floor_price, 0);
+    }
+
+    #[test]
+    fn remove_listing_ignores_unknown_listings() {
+        let (mut stats, _) = full_book();
+        stats.remove_listing(Pubkey::new_unique());
+        assert_eq!(stats.floor_book.len(), MAX_FLOOR_LISTINGS);
+        assert_eq!(stats.floor_price, 1);
+    }
+
+    #[test]
+    fn floor_book_evicts_most_expensive_when_full() {
+        let (mut stats, listings) = full_book();
+        assert!(!stats.untracked);
+        
+        let cheapest = Pubkey::new_unique();
+        stats.offer_floor(cheapest, 0);
+        assert_eq!(stats.floor_book.len(), MAX_FLOOR_LISTINGS);
+        assert_eq!(stats.floor_listing, Some(cheapest));
+        assert!(stats.floor_book.iter().all(|entry| entry.listing != listings[MAX_FLOOR_LISTINGS - 1]));
+        assert!(stats.untracked);
+    }
+
+    #[test]
+    fn floor_book_leaves_out_listings_past_the_end() {
+        let (mut stats, _) = full_book();
+        let expensive = Pubkey::new_unique();
+        stats.offer_floor(expensive, MAX_FLOOR_LISTINGS as u64 + 1);
+        assert_eq!(stats.floor_book.len(), MAX_FLOOR_LISTINGS);
+        assert!(stats.floor_book.iter().all(|entry| entry.listing != expensive));
+        assert!(stats.untracked);
+    }
+
+    #[test]
+    fn reprice_floor_ignores_listings_outside_book() {
+        let mut stats = collection_stats();
+        let listing = Pubkey::new_unique();
+        stats.offer_floor(listing, 100);
+        stats.reprice_floor(Pubkey::new_unique(), 1);
+        assert_eq!(book(&stats), vec![100]);
+        assert_eq!(stats.floor_listing, Some(listing));
+    }
+
+    #[test]
+    fn reprice_floor_moves_listing() {
+        let mut stats = collection_stats();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        stats.offer_floor(a, 100);
+        stats.offer_floor(b, 200);
+        
+        stats.reprice_floor(b, 50);
+        assert_eq!(stats.floor_listing, Some(b));
+        
+        // Without listings outside the book a raised listing stays tracked
+        stats.reprice_floor(b, 500);
+        assert_eq!(book(&stats), vec![100, 500]);
+        assert_eq!(stats.floor_listing, Some(a));
+    }
+
+    #[test]
+    fn reprice_floor_drops_raised_listing_when_untracked_exist() {
+        let (mut stats, listings) = full_book();
+        stats.offer_floor(Pubkey::new_unique(), 1_000);
+        assert!(stats.untracked);
+        
+        // Raised past the end, the listing may no longer be among the cheapest
+        stats.reprice_floor(listings[0], 1_000);
+        assert_eq!(stats.floor_book.len(), MAX_FLOOR_LISTINGS - 1);
+        assert_eq!(stats.floor_listing, Some(listings[1]));
+        
+        // Raised within the book, it keeps its place
+        stats.reprice_floor(listings[1], 3);
+        assert_eq!(stats.floor_listing, Some(listings[2]));
+        assert_eq!(stats.floor_book.len(), MAX_FLOOR_LISTINGS - 1);
+    }
+}